@@ -0,0 +1,47 @@
+//! Time to verify one seal, which is one hash of its `Compute`, for SHA3-256 and for Argon2d at
+//! the default and the largest parameters a chain spec may give.
+//!
+//! Run with `cargo bench -p sha3pow`.
+
+use std::time::{Duration, Instant};
+
+use sha3pow::{Argon2Params, Compute, MAX_ITERATIONS, MAX_MEMORY_KIB};
+use sp_core::{H256, U256};
+
+fn compute(nonce: u64) -> Compute {
+	Compute {
+		difficulty: U256::from(1_000_000),
+		pre_hash: H256::repeat_byte(7),
+		nonce: H256::from_low_u64_be(nonce),
+	}
+}
+
+/// Average time of `f` over `runs` runs
+fn time(runs: u64, mut f: impl FnMut(u64)) -> Duration {
+	let start = Instant::now();
+	for run in 0..runs {
+		f(run);
+	}
+	start.elapsed() / runs as u32
+}
+
+fn main() {
+	let seed = H256::repeat_byte(42);
+
+	let sha3 = time(100_000, |nonce| { compute(nonce).compute(); });
+	println!("sha3-256:                      {:?} per seal", sha3);
+
+	let default = Argon2Params::default();
+	let argon2 = time(200, |nonce| { default.seal(&seed, compute(nonce)); });
+	println!(
+		"argon2d {:>6} KiB, {} pass:    {:?} per seal",
+		default.memory_kib, default.iterations, argon2,
+	);
+
+	let max = Argon2Params { memory_kib: MAX_MEMORY_KIB, iterations: MAX_ITERATIONS, ..default };
+	let argon2 = time(10, |nonce| { max.seal(&seed, compute(nonce)); });
+	println!(
+		"argon2d {:>6} KiB, {} passes:  {:?} per seal",
+		max.memory_kib, max.iterations, argon2,
+	);
+}
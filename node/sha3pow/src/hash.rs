@@ -0,0 +1,143 @@
+//! Hash functions the work of a seal can be computed with
+
+use serde::{Deserialize, Serialize};
+use sha3::Digest;
+use sp_core::{hashing, H256};
+
+/// A hash function that turns an encoded `Compute` into the work of a seal
+pub trait PowHash {
+	/// Hash of `data`
+	fn hash(&self, data: &[u8]) -> H256;
+}
+
+/// SHA3-256, as standardised in FIPS 202
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Sha3_256;
+
+impl PowHash for Sha3_256 {
+	fn hash(&self, data: &[u8]) -> H256 {
+		H256::from_slice(sha3::Sha3_256::digest(data).as_slice())
+	}
+}
+
+/// Keccak-256, the SHA3 submission with its original padding as used by Ethereum
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Keccak256;
+
+impl PowHash for Keccak256 {
+	fn hash(&self, data: &[u8]) -> H256 {
+		H256::from_slice(sha3::Keccak256::digest(data).as_slice())
+	}
+}
+
+/// Blake2b with a 256 bit output
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Blake2b256;
+
+impl PowHash for Blake2b256 {
+	fn hash(&self, data: &[u8]) -> H256 {
+		H256::from(hashing::blake2_256(data))
+	}
+}
+
+/// SHA-256 applied twice, as in Bitcoin
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DoubleSha256;
+
+impl PowHash for DoubleSha256 {
+	fn hash(&self, data: &[u8]) -> H256 {
+		H256::from(hashing::sha2_256(&hashing::sha2_256(data)))
+	}
+}
+
+/// One of the hash functions above, picked at runtime, e.g. from a chain spec
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PowHashKind {
+	#[serde(rename = "sha3-256")]
+	Sha3_256,
+	#[serde(rename = "keccak-256")]
+	Keccak256,
+	#[serde(rename = "blake2b-256")]
+	Blake2b256,
+	#[serde(rename = "double-sha256")]
+	DoubleSha256,
+}
+
+impl Default for PowHashKind {
+	fn default() -> Self {
+		PowHashKind::Sha3_256
+	}
+}
+
+impl PowHash for PowHashKind {
+	fn hash(&self, data: &[u8]) -> H256 {
+		match self {
+			PowHashKind::Sha3_256 => Sha3_256.hash(data),
+			PowHashKind::Keccak256 => Keccak256.hash(data),
+			PowHashKind::Blake2b256 => Blake2b256.hash(data),
+			PowHashKind::DoubleSha256 => DoubleSha256.hash(data),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn hex(hash: &str) -> H256 {
+		hash.parse().unwrap()
+	}
+
+	// Digests of the empty string and of "abc", from the specifications or reference
+	// implementations of each hash function
+	fn assert_known_answers(hash: impl PowHash, empty: &str, abc: &str) {
+		assert_eq!(hash.hash(b""), hex(empty));
+		assert_eq!(hash.hash(b"abc"), hex(abc));
+	}
+
+	#[test]
+	fn sha3_256_known_answers() {
+		assert_known_answers(
+			Sha3_256,
+			"a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a",
+			"3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+		);
+	}
+
+	#[test]
+	fn keccak_256_known_answers() {
+		assert_known_answers(
+			Keccak256,
+			"c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+			"4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+		);
+	}
+
+	#[test]
+	fn blake2b_256_known_answers() {
+		assert_known_answers(
+			Blake2b256,
+			"0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8",
+			"bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+		);
+	}
+
+	#[test]
+	fn double_sha256_known_answers() {
+		assert_known_answers(
+			DoubleSha256,
+			"5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456",
+			"4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358",
+		);
+	}
+
+	#[test]
+	fn kinds_hash_as_their_functions() {
+		for data in &[&b""[..], b"abc", &[7; 200]] {
+			assert_eq!(PowHashKind::Sha3_256.hash(data), Sha3_256.hash(data));
+			assert_eq!(PowHashKind::Keccak256.hash(data), Keccak256.hash(data));
+			assert_eq!(PowHashKind::Blake2b256.hash(data), Blake2b256.hash(data));
+			assert_eq!(PowHashKind::DoubleSha256.hash(data), DoubleSha256.hash(data));
+		}
+	}
+}
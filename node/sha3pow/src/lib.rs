@@ -4,18 +4,23 @@ use sp_runtime::traits::Block as BlockT;
 use parity_scale_codec::{Encode, Decode};
 use sc_consensus_pow::{PowAlgorithm, Error};
 use sp_consensus_pow::{Seal as RawSeal, DifficultyApi};
-use sha3::{Sha3_256, Digest};
 use rand::{thread_rng, SeedableRng, rngs::SmallRng};
 use std::sync::Arc;
 use sp_blockchain::HeaderBackend;
 use sc_client_api::backend::AuxStore;
 use sp_api::ProvideRuntimeApi;
 
+mod hash;
+mod memory_hard;
+
+pub use hash::{Blake2b256, DoubleSha256, Keccak256, PowHash, PowHashKind, Sha3_256};
+pub use memory_hard::{Argon2Algorithm, Argon2Params, MAX_ITERATIONS, MAX_MEMORY_KIB};
+
 /// Determine whether the given hash satisfies the given difficulty.
 /// The test is done by multiplying the two together. If the product
 /// overflows the bounds of U256, then the product (and thus the hash)
 /// was too high.
-fn hash_meets_difficulty(hash: &H256, difficulty: U256) -> bool {
+pub fn hash_meets_difficulty(hash: &H256, difficulty: U256) -> bool {
 	let num_hash = U256::from(&hash[..]);
 	let (_, overflowed) = num_hash.overflowing_mul(difficulty);
 
@@ -41,39 +46,71 @@ pub struct Compute {
 }
 
 impl Compute {
+	/// Compute the seal with SHA3-256
 	pub fn compute(self) -> Seal {
-		let work = H256::from_slice(Sha3_256::digest(&self.encode()[..]).as_slice());
+		self.compute_with(&Sha3_256)
+	}
+
+	/// Compute the seal with the given hash function
+	pub fn compute_with<H: PowHash>(self, hash: &H) -> Seal {
+		let work = hash.hash(&self.encode()[..]);
 
 		Seal {
 			nonce: self.nonce,
 			difficulty: self.difficulty,
-			work: work,
+			work,
 		}
 	}
 }
 
-/// A complete PoW Algorithm that uses Sha3 hashing.
+/// A PoW algorithm whose seals are found by trying nonces one after another, so several
+/// miners can split the nonce space between them.
+pub trait NonceSearch<B: BlockT> {
+	/// The seal of `nonce` for a block on top of `parent`, whether or not it meets `difficulty`
+	fn seal(&self, parent: &H256, pre_hash: &H256, difficulty: U256, nonce: H256) -> Result<Seal, Error<B>>;
+
+	/// Try up to `rounds` nonces counting up from `nonce`, and return the seal of the first
+	/// one that meets `difficulty`. `nonce` is left after the last nonce tried.
+	fn search(
+		&self,
+		parent: &H256,
+		pre_hash: &H256,
+		difficulty: U256,
+		nonce: &mut U256,
+		rounds: u32,
+	) -> Result<Option<RawSeal>, Error<B>>;
+}
+
+/// A complete PoW Algorithm that uses Sha3 hashing, or another `PowHash`.
 /// Needs a reference to the client so it can grab the difficulty from the runtime.
-pub struct Sha3Algorithm<C> {
+pub struct Sha3Algorithm<C, H = Sha3_256> {
 	client: Arc<C>,
+	hash: H,
 }
 
 impl<C> Sha3Algorithm<C> {
 	pub fn new(client: Arc<C>) -> Self {
-		Self { client }
+		Self::with_hash(client, Sha3_256)
+	}
+}
+
+impl<C, H> Sha3Algorithm<C, H> {
+	/// Compute seals with `hash` rather than SHA3-256
+	pub fn with_hash(client: Arc<C>, hash: H) -> Self {
+		Self { client, hash }
 	}
 }
 
 // Manually implement clone. Deriving doesn't work because
 // it'll derive impl<C: Clone> Clone for Sha3Algorithm<C>. But C in practice isn't Clone.
-impl<C> Clone for Sha3Algorithm<C> {
+impl<C, H: Clone> Clone for Sha3Algorithm<C, H> {
 	fn clone(&self) -> Self {
-		Self::new(self.client.clone())
+		Self::with_hash(self.client.clone(), self.hash.clone())
 	}
 }
 
 // Here we implement the general PowAlgorithm trait for our concrete Sha3Algorithm
-impl<B: BlockT<Hash=H256>, C> PowAlgorithm<B> for Sha3Algorithm<C> where
+impl<B: BlockT<Hash=H256>, C, H: PowHash> PowAlgorithm<B> for Sha3Algorithm<C, H> where
 	C: HeaderBackend<B> + AuxStore + ProvideRuntimeApi<B>,
 	C::Api: DifficultyApi<B, U256>,
 {
@@ -113,7 +150,7 @@ impl<B: BlockT<Hash=H256>, C> PowAlgorithm<B> for Sha3Algorithm<C> where
 			nonce: seal.nonce,
 		};
 
-		if compute.compute() != seal {
+		if compute.compute_with(&self.hash) != seal {
 			return Ok(false)
 		}
 
@@ -144,7 +181,7 @@ impl<B: BlockT<Hash=H256>, C> PowAlgorithm<B> for Sha3Algorithm<C> where
 				pre_hash: *pre_hash,
 				nonce,
 			};
-			let seal = compute.compute();
+			let seal = compute.compute_with(&self.hash);
 
 			// If we solved the PoW then return, otherwise loop again
 			if hash_meets_difficulty(&seal.work, difficulty) {
@@ -156,3 +193,146 @@ impl<B: BlockT<Hash=H256>, C> PowAlgorithm<B> for Sha3Algorithm<C> where
 		Ok(None)
 	}
 }
+
+impl<B: BlockT, C, H: PowHash> NonceSearch<B> for Sha3Algorithm<C, H> {
+	fn seal(&self, _parent: &H256, pre_hash: &H256, difficulty: U256, nonce: H256) -> Result<Seal, Error<B>> {
+		Ok(Compute {
+			difficulty,
+			pre_hash: *pre_hash,
+			nonce,
+		}.compute_with(&self.hash))
+	}
+
+	fn search(
+		&self,
+		_parent: &H256,
+		pre_hash: &H256,
+		difficulty: U256,
+		nonce: &mut U256,
+		rounds: u32,
+	) -> Result<Option<RawSeal>, Error<B>> {
+		for _ in 0..rounds {
+			let mut nonce_bytes = [0u8; 32];
+			nonce.to_big_endian(&mut nonce_bytes);
+			*nonce = nonce.overflowing_add(U256::one()).0;
+
+			let seal = Compute {
+				difficulty,
+				pre_hash: *pre_hash,
+				nonce: H256::from(nonce_bytes),
+			}.compute_with(&self.hash);
+
+			if hash_meets_difficulty(&seal.work, difficulty) {
+				return Ok(Some(seal.encode()))
+			}
+		}
+
+		Ok(None)
+	}
+}
+
+/// The PoW algorithm a chain picks: a plain hash, or the memory-hard Argon2d
+pub enum ChainAlgorithm<C> {
+	Hash(Sha3Algorithm<C, PowHashKind>),
+	Argon2(Argon2Algorithm<C>),
+}
+
+impl<C> Clone for ChainAlgorithm<C> {
+	fn clone(&self) -> Self {
+		match self {
+			ChainAlgorithm::Hash(algorithm) => ChainAlgorithm::Hash(algorithm.clone()),
+			ChainAlgorithm::Argon2(algorithm) => ChainAlgorithm::Argon2(algorithm.clone()),
+		}
+	}
+}
+
+impl<B: BlockT<Hash=H256>, C> PowAlgorithm<B> for ChainAlgorithm<C> where
+	C: HeaderBackend<B> + AuxStore + ProvideRuntimeApi<B>,
+	C::Api: DifficultyApi<B, U256>,
+{
+	type Difficulty = U256;
+
+	fn difficulty(&self, parent: B::Hash) -> Result<Self::Difficulty, Error<B>> {
+		match self {
+			ChainAlgorithm::Hash(algorithm) => algorithm.difficulty(parent),
+			ChainAlgorithm::Argon2(algorithm) => algorithm.difficulty(parent),
+		}
+	}
+
+	fn verify(
+		&self,
+		parent: &BlockId<B>,
+		pre_hash: &H256,
+		pre_digest: Option<&[u8]>,
+		seal: &RawSeal,
+		difficulty: Self::Difficulty
+	) -> Result<bool, Error<B>> {
+		match self {
+			ChainAlgorithm::Hash(algorithm) => algorithm.verify(parent, pre_hash, pre_digest, seal, difficulty),
+			ChainAlgorithm::Argon2(algorithm) => algorithm.verify(parent, pre_hash, pre_digest, seal, difficulty),
+		}
+	}
+
+	fn mine(
+		&self,
+		parent: &BlockId<B>,
+		pre_hash: &H256,
+		pre_digest: Option<&[u8]>,
+		difficulty: Self::Difficulty,
+		round: u32
+	) -> Result<Option<RawSeal>, Error<B>> {
+		match self {
+			ChainAlgorithm::Hash(algorithm) => algorithm.mine(parent, pre_hash, pre_digest, difficulty, round),
+			ChainAlgorithm::Argon2(algorithm) => algorithm.mine(parent, pre_hash, pre_digest, difficulty, round),
+		}
+	}
+}
+
+impl<B: BlockT<Hash=H256>, C: HeaderBackend<B>> NonceSearch<B> for ChainAlgorithm<C> {
+	fn seal(&self, parent: &H256, pre_hash: &H256, difficulty: U256, nonce: H256) -> Result<Seal, Error<B>> {
+		match self {
+			ChainAlgorithm::Hash(algorithm) => algorithm.seal(parent, pre_hash, difficulty, nonce),
+			ChainAlgorithm::Argon2(algorithm) => algorithm.seal(parent, pre_hash, difficulty, nonce),
+		}
+	}
+
+	fn search(
+		&self,
+		parent: &H256,
+		pre_hash: &H256,
+		difficulty: U256,
+		nonce: &mut U256,
+		rounds: u32,
+	) -> Result<Option<RawSeal>, Error<B>> {
+		match self {
+			ChainAlgorithm::Hash(algorithm) => algorithm.search(parent, pre_hash, difficulty, nonce, rounds),
+			ChainAlgorithm::Argon2(algorithm) => algorithm.search(parent, pre_hash, difficulty, nonce, rounds),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sha3::Digest;
+
+	fn compute() -> Compute {
+		Compute {
+			difficulty: U256::from(1_000_000),
+			pre_hash: H256::repeat_byte(7),
+			nonce: H256::from_low_u64_be(42),
+		}
+	}
+
+	#[test]
+	fn compute_hashes_the_encoded_compute_with_sha3_256() {
+		let seal = compute().compute();
+		let work = H256::from_slice(sha3::Sha3_256::digest(&compute().encode()).as_slice());
+		assert_eq!(seal, Seal { difficulty: U256::from(1_000_000), work, nonce: H256::from_low_u64_be(42) });
+
+		// The work of seals mined before the hash function could be chosen
+		let baseline: H256 = "5d5ffb1ec4d8ed1dae4edd2b03dd266b3ed7ed7f1c7ad6b3262e906024977d47".parse().unwrap();
+		assert_eq!(seal.work, baseline);
+		assert_eq!(compute().compute_with(&PowHashKind::Sha3_256), seal);
+	}
+}
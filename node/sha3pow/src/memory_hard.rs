@@ -0,0 +1,425 @@
+//! A memory-hard alternative to `Sha3Algorithm`. The work of a seal is the Argon2d hash of the
+//! encoded `Compute`, salted with a seed that changes every epoch, so every hash tried has to fill
+//! and read back a block of memory.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+};
+
+use parity_scale_codec::{Decode, Encode};
+use rand::{rngs::SmallRng, thread_rng, SeedableRng};
+use sc_client_api::backend::AuxStore;
+use sc_consensus_pow::{Error, PowAlgorithm};
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus_pow::{DifficultyApi, Seal as RawSeal};
+use sp_core::{H256, U256};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, Header as HeaderT, UniqueSaturatedInto},
+};
+
+use crate::{hash_meets_difficulty, Compute, NonceSearch, Seal};
+
+/// Upper bound on the memory one hash may use, which bounds the cost of verifying a seal
+pub const MAX_MEMORY_KIB: u32 = 64 * 1024;
+
+/// Upper bound on the passes over memory one hash may make
+pub const MAX_ITERATIONS: u32 = 4;
+
+/// Number of epoch seeds remembered before the cache is cleared
+const MAX_CACHED_SEEDS: usize = 16 * 1024;
+
+/// Parameters of the Argon2d seal computation
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Argon2Params {
+	/// KiB of memory each hash fills
+	pub memory_kib: u32,
+	/// Passes each hash makes over its memory
+	pub iterations: u32,
+	/// Number of blocks sealed with the same seed. The seed of a block is the hash of its most
+	/// recent ancestor whose number is a multiple of this.
+	pub epoch_length: u32,
+}
+
+impl Default for Argon2Params {
+	fn default() -> Self {
+		Self {
+			memory_kib: 1024,
+			iterations: 1,
+			epoch_length: 256,
+		}
+	}
+}
+
+impl Argon2Params {
+	/// Check the parameters are within the bounds that keep verification cheap
+	pub fn validate(&self) -> Result<(), String> {
+		if self.memory_kib < 8 || self.memory_kib > MAX_MEMORY_KIB {
+			return Err(format!("Argon2 memory must be between 8 and {} KiB", MAX_MEMORY_KIB));
+		}
+		if self.iterations < 1 || self.iterations > MAX_ITERATIONS {
+			return Err(format!("Argon2 iterations must be between 1 and {}", MAX_ITERATIONS));
+		}
+		if self.epoch_length < 1 {
+			return Err("Argon2 epoch length must be at least 1".into());
+		}
+		Ok(())
+	}
+
+	/// Compute the seal of `compute` in the epoch with seed `seed`
+	pub fn seal(&self, seed: &H256, compute: Compute) -> Seal {
+		let config = argon2::Config {
+			variant: argon2::Variant::Argon2d,
+			version: argon2::Version::Version13,
+			mem_cost: self.memory_kib,
+			time_cost: self.iterations,
+			lanes: 1,
+			thread_mode: argon2::ThreadMode::Sequential,
+			secret: &[],
+			ad: &[],
+			hash_length: 32,
+		};
+		let work = argon2::hash_raw(&compute.encode(), seed.as_bytes(), &config)
+			.expect("parameters are validated and the salt is 32 bytes; qed");
+
+		Seal {
+			nonce: compute.nonce,
+			difficulty: compute.difficulty,
+			work: H256::from_slice(&work),
+		}
+	}
+}
+
+/// A complete PoW Algorithm that uses Argon2d hashing.
+/// Needs a reference to the client so it can grab the difficulty from the runtime and find
+/// epoch seeds.
+pub struct Argon2Algorithm<C> {
+	client: Arc<C>,
+	params: Argon2Params,
+	/// Seed of the blocks on top of a block, by the block's hash
+	seeds: Arc<Mutex<HashMap<H256, H256>>>,
+}
+
+impl<C> Argon2Algorithm<C> {
+	pub fn new(client: Arc<C>, params: Argon2Params) -> Self {
+		Self { client, params, seeds: Default::default() }
+	}
+}
+
+impl<C> Clone for Argon2Algorithm<C> {
+	fn clone(&self) -> Self {
+		Self {
+			client: self.client.clone(),
+			params: self.params,
+			seeds: self.seeds.clone(),
+		}
+	}
+}
+
+impl<C> Argon2Algorithm<C> {
+	/// Seed of the blocks on top of `parent`. Walks back to the start of the epoch, or to the
+	/// first block whose seed is known.
+	fn seed<B>(&self, parent: H256) -> Result<H256, Error<B>> where
+		B: BlockT<Hash=H256>,
+		C: HeaderBackend<B>,
+	{
+		let mut walked = Vec::new();
+		let mut hash = parent;
+		let seed = loop {
+			if let Some(seed) = self.seeds.lock().expect("seed cache lock poisoned").get(&hash) {
+				break *seed
+			}
+			let header = self.client.header(BlockId::Hash(hash))
+				.map_err(Error::Client)?
+				.ok_or_else(|| Error::Environment(format!("Header {} not found for epoch seed", hash)))?;
+			walked.push(hash);
+			let number = UniqueSaturatedInto::<u64>::unique_saturated_into(*header.number());
+			if number % u64::from(self.params.epoch_length) == 0 {
+				break hash
+			}
+			hash = *header.parent_hash();
+		};
+
+		let mut seeds = self.seeds.lock().expect("seed cache lock poisoned");
+		if seeds.len() + walked.len() > MAX_CACHED_SEEDS {
+			seeds.clear();
+		}
+		seeds.extend(walked.into_iter().map(|hash| (hash, seed)));
+		Ok(seed)
+	}
+}
+
+impl<B: BlockT<Hash=H256>, C> PowAlgorithm<B> for Argon2Algorithm<C> where
+	C: HeaderBackend<B> + AuxStore + ProvideRuntimeApi<B>,
+	C::Api: DifficultyApi<B, U256>,
+{
+	type Difficulty = U256;
+
+	fn difficulty(&self, parent: B::Hash) -> Result<Self::Difficulty, Error<B>> {
+		let parent_id = BlockId::<B>::hash(parent);
+		self.client.runtime_api().difficulty(&parent_id)
+			.map_err(|e| sc_consensus_pow::Error::Environment(
+				format!("Fetching difficulty from runtime failed: {:?}", e)
+			))
+	}
+
+	fn verify(
+		&self,
+		parent: &BlockId<B>,
+		pre_hash: &H256,
+		_pre_digest: Option<&[u8]>,
+		seal: &RawSeal,
+		difficulty: Self::Difficulty
+	) -> Result<bool, Error<B>> {
+		let seal = match Seal::decode(&mut &seal[..]) {
+			Ok(seal) => seal,
+			Err(_) => return Ok(false),
+		};
+
+		// Checking the claimed work is cheap, so fail fast before the memory-hard hash
+		if !hash_meets_difficulty(&seal.work, difficulty) {
+			return Ok(false)
+		}
+
+		let parent = self.client.expect_block_hash_from_id(parent).map_err(Error::Client)?;
+		Ok(self.seal(&parent, pre_hash, difficulty, seal.nonce)? == seal)
+	}
+
+	fn mine(
+		&self,
+		parent: &BlockId<B>,
+		pre_hash: &H256,
+		_pre_digest: Option<&[u8]>,
+		difficulty: Self::Difficulty,
+		round: u32
+	) -> Result<Option<RawSeal>, Error<B>> {
+		let mut rng = SmallRng::from_rng(&mut thread_rng())
+			.map_err(|e| Error::Environment(format!("Initialize RNG failed for mining: {:?}", e)))?;
+		let parent = self.client.expect_block_hash_from_id(parent).map_err(Error::Client)?;
+		let seed = self.seed(parent)?;
+
+		for _ in 0..round {
+			let seal = self.params.seal(&seed, Compute {
+				difficulty,
+				pre_hash: *pre_hash,
+				nonce: H256::random_using(&mut rng),
+			});
+
+			if hash_meets_difficulty(&seal.work, difficulty) {
+				return Ok(Some(seal.encode()))
+			}
+		}
+
+		Ok(None)
+	}
+}
+
+impl<B: BlockT<Hash=H256>, C: HeaderBackend<B>> NonceSearch<B> for Argon2Algorithm<C> {
+	fn seal(&self, parent: &H256, pre_hash: &H256, difficulty: U256, nonce: H256) -> Result<Seal, Error<B>> {
+		let seed = self.seed(*parent)?;
+		Ok(self.params.seal(&seed, Compute { difficulty, pre_hash: *pre_hash, nonce }))
+	}
+
+	fn search(
+		&self,
+		parent: &H256,
+		pre_hash: &H256,
+		difficulty: U256,
+		nonce: &mut U256,
+		rounds: u32,
+	) -> Result<Option<RawSeal>, Error<B>> {
+		let seed = self.seed(*parent)?;
+		for _ in 0..rounds {
+			let mut nonce_bytes = [0u8; 32];
+			nonce.to_big_endian(&mut nonce_bytes);
+			*nonce = nonce.overflowing_add(U256::one()).0;
+
+			let seal = self.params.seal(&seed, Compute {
+				difficulty,
+				pre_hash: *pre_hash,
+				nonce: H256::from(nonce_bytes),
+			});
+
+			if hash_meets_difficulty(&seal.work, difficulty) {
+				return Ok(Some(seal.encode()))
+			}
+		}
+
+		Ok(None)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_blockchain::{BlockStatus, Info};
+	use sp_runtime::{
+		testing::{Block as RawBlock, ExtrinsicWrapper, Header},
+		traits::NumberFor,
+	};
+
+	type Block = RawBlock<ExtrinsicWrapper<()>>;
+
+	const PARAMS: Argon2Params = Argon2Params { memory_kib: 8, iterations: 1, epoch_length: 4 };
+
+	/// A chain of headers, without runtime or storage
+	struct Chain {
+		headers: HashMap<H256, Header>,
+	}
+
+	/// Runtime API of `Chain`, only there to satisfy the bounds of `PowAlgorithm`
+	struct ChainApi;
+
+	sp_api::mock_impl_runtime_apis! {
+		impl DifficultyApi<Block, U256> for ChainApi {
+			fn difficulty() -> U256 {
+				unimplemented!("Not needed to mine or verify")
+			}
+		}
+	}
+
+	impl ProvideRuntimeApi<Block> for Chain {
+		type Api = ChainApi;
+
+		fn runtime_api(&self) -> sp_api::ApiRef<'_, Self::Api> {
+			ChainApi.into()
+		}
+	}
+
+	impl HeaderBackend<Block> for Chain {
+		fn header(&self, id: BlockId<Block>) -> sp_blockchain::Result<Option<Header>> {
+			match id {
+				BlockId::Hash(hash) => Ok(self.headers.get(&hash).cloned()),
+				BlockId::Number(_) => unimplemented!("Seeds are found by hash"),
+			}
+		}
+
+		fn info(&self) -> Info<Block> {
+			unimplemented!("Not needed to mine or verify")
+		}
+
+		fn status(&self, _id: BlockId<Block>) -> sp_blockchain::Result<BlockStatus> {
+			unimplemented!("Not needed to mine or verify")
+		}
+
+		fn number(&self, _hash: H256) -> sp_blockchain::Result<Option<NumberFor<Block>>> {
+			unimplemented!("Not needed to mine or verify")
+		}
+
+		fn hash(&self, _number: NumberFor<Block>) -> sp_blockchain::Result<Option<H256>> {
+			unimplemented!("Not needed to mine or verify")
+		}
+	}
+
+	impl AuxStore for Chain {
+		fn insert_aux<
+			'a,
+			'b: 'a,
+			'c: 'a,
+			I: IntoIterator<Item=&'a(&'c [u8], &'c [u8])>,
+			D: IntoIterator<Item=&'a &'b [u8]>,
+		>(&self, _insert: I, _delete: D) -> sp_blockchain::Result<()> {
+			unimplemented!("Not needed to mine or verify")
+		}
+
+		fn get_aux(&self, _key: &[u8]) -> sp_blockchain::Result<Option<Vec<u8>>> {
+			unimplemented!("Not needed to mine or verify")
+		}
+	}
+
+	/// An algorithm on a chain of `length` blocks from genesis, and the hashes of the blocks
+	fn on_chain(length: u64) -> (Argon2Algorithm<Chain>, Vec<H256>) {
+		let mut headers = HashMap::new();
+		let mut hashes: Vec<H256> = Vec::new();
+		for number in 0..length {
+			let header = Header {
+				parent_hash: hashes.last().cloned().unwrap_or_default(),
+				number,
+				state_root: Default::default(),
+				extrinsics_root: Default::default(),
+				digest: Default::default(),
+			};
+			hashes.push(header.hash());
+			headers.insert(header.hash(), header);
+		}
+		(Argon2Algorithm::new(Arc::new(Chain { headers }), PARAMS), hashes)
+	}
+
+	fn seed(algorithm: &Argon2Algorithm<Chain>, parent: H256) -> H256 {
+		algorithm.seed::<Block>(parent).unwrap()
+	}
+
+	#[test]
+	fn validate_bounds() {
+		let valid = |params: Argon2Params| params.validate().is_ok();
+		let default = Argon2Params::default();
+		assert!(valid(default));
+
+		assert!(!valid(Argon2Params { memory_kib: 7, ..default }));
+		assert!(valid(Argon2Params { memory_kib: 8, ..default }));
+		assert!(valid(Argon2Params { memory_kib: MAX_MEMORY_KIB, ..default }));
+		assert!(!valid(Argon2Params { memory_kib: MAX_MEMORY_KIB + 1, ..default }));
+
+		assert!(!valid(Argon2Params { iterations: 0, ..default }));
+		assert!(valid(Argon2Params { iterations: 1, ..default }));
+		assert!(valid(Argon2Params { iterations: MAX_ITERATIONS, ..default }));
+		assert!(!valid(Argon2Params { iterations: MAX_ITERATIONS + 1, ..default }));
+
+		assert!(!valid(Argon2Params { epoch_length: 0, ..default }));
+		assert!(valid(Argon2Params { epoch_length: 1, ..default }));
+	}
+
+	#[test]
+	fn seed_is_the_latest_epoch_start() {
+		let (algorithm, hashes) = on_chain(13);
+		for (parent, start) in [(0, 0), (3, 0), (4, 4), (5, 4), (7, 4), (8, 8), (12, 12)].iter() {
+			assert_eq!(seed(&algorithm, hashes[*parent]), hashes[*start], "parent {}", parent);
+		}
+	}
+
+	#[test]
+	fn seed_is_the_same_from_the_cache() {
+		let (algorithm, hashes) = on_chain(13);
+		// Walking back from the end of an epoch caches the seed of the whole epoch
+		assert_eq!(seed(&algorithm, hashes[11]), hashes[8]);
+		assert_eq!(algorithm.seeds.lock().unwrap().len(), 4);
+
+		let (uncached, _) = on_chain(13);
+		for parent in (0..13).rev() {
+			assert_eq!(seed(&algorithm, hashes[parent]), seed(&uncached, hashes[parent]), "parent {}", parent);
+		}
+	}
+
+	#[test]
+	fn mined_seals_verify() {
+		let (algorithm, hashes) = on_chain(13);
+		let difficulty = U256::from(4);
+		let pre_hash = H256::repeat_byte(7);
+		let verify = |parent: H256, seal: &RawSeal| {
+			PowAlgorithm::<Block>::verify(&algorithm, &BlockId::Hash(parent), &pre_hash, None, seal, difficulty).unwrap()
+		};
+
+		let seal = PowAlgorithm::<Block>::mine(&algorithm, &BlockId::Hash(hashes[5]), &pre_hash, None, difficulty, 1_000)
+			.unwrap()
+			.expect("a seal meeting a difficulty of 4 is found in 1000 tries");
+		assert!(verify(hashes[5], &seal));
+		// Blocks of the same epoch are sealed with the same seed, other epochs are not
+		assert!(verify(hashes[7], &seal));
+		assert!(!verify(hashes[8], &seal));
+
+		// Nor is a seal valid for another nonce
+		let mut tampered = Seal::decode(&mut &seal[..]).unwrap();
+		tampered.nonce = H256::repeat_byte(1);
+		assert!(!verify(hashes[5], &tampered.encode()));
+
+		let mut nonce = U256::zero();
+		let seal = NonceSearch::<Block>::search(&algorithm, &hashes[9], &pre_hash, difficulty, &mut nonce, 1_000)
+			.unwrap()
+			.expect("a seal meeting a difficulty of 4 is found in 1000 tries");
+		assert!(verify(hashes[9], &seal));
+	}
+}
@@ -4,14 +4,40 @@ use utxo_runtime::{
 	SudoConfig, SystemConfig, WASM_BINARY, Signature, UtxoConfig,
 };
 use sc_service;
+use sc_chain_spec::ChainSpecExtension;
+use serde::{Deserialize, Serialize};
+use sha3pow::{Argon2Algorithm, Argon2Params, ChainAlgorithm, PowHashKind, Sha3Algorithm};
+use std::sync::Arc;
 use sp_runtime::traits::{Verify, IdentifyAccount};
-use utxo_runtime::utxo;
+use utxo_runtime::{script::Script, utxo};
 
 // Note this is the URL for the telemetry server
 //const STAGING_TELEMETRY_URL: &str = "wss://telemetry.polkadot.io/submit/";
 
+/// Node settings the chain spec carries besides the genesis state
+#[derive(Default, Clone, Serialize, Deserialize, ChainSpecExtension)]
+#[serde(rename_all = "camelCase")]
+pub struct Extensions {
+	/// Hash function of the proof of work, SHA3-256 unless given
+	#[serde(default)]
+	pub pow_hash: PowHashKind,
+	/// Seal with the memory-hard Argon2d instead of `pow_hash`, if given
+	#[serde(default)]
+	pub argon2: Option<Argon2Params>,
+}
+
+impl Extensions {
+	/// The PoW algorithm these settings pick, for `client`
+	pub fn pow_algorithm<C>(&self, client: Arc<C>) -> ChainAlgorithm<C> {
+		match self.argon2 {
+			Some(params) => ChainAlgorithm::Argon2(Argon2Algorithm::new(client, params)),
+			None => ChainAlgorithm::Hash(Sha3Algorithm::with_hash(client, self.pow_hash)),
+		}
+	}
+}
+
 /// Specialized `ChainSpec`. This is a specialization of the general Substrate ChainSpec type.
-pub type ChainSpec = sc_service::GenericChainSpec<GenesisConfig>;
+pub type ChainSpec = sc_service::GenericChainSpec<GenesisConfig, Extensions>;
 
 /// Helper function to generate a crypto pair from seed
 pub fn get_from_seed<TPublic: Public>(seed: &str) -> <TPublic::Pair as Pair>::Public {
@@ -53,7 +79,7 @@ pub fn development_config() -> ChainSpec {
 		None,
 		None,
 		None,
-		None
+		Extensions::default(),
 	)
 }
 
@@ -89,7 +115,7 @@ pub fn local_testnet_config() -> ChainSpec {
 		None,
 		None,
 		None,
-		None
+		Extensions::default(),
 	)
 }
 
@@ -101,10 +127,10 @@ fn testnet_genesis(
 ) -> GenesisConfig {
 	// This prints upon creation of the genesis block
 	println!("============ HELPER INPUTS FOR THE UI DEMO ============");
-	println!("OUTPOINT (Alice's UTXO Hash): 0x76584168d10a20084082ed80ec71e2a783abbb8dd6eb9d4893b089228498e9ff\n");
-	println!("SIGSCRIPT (Alice Signature on a transaction where she spends 50 utxo on Bob): 0x6ceab99702c60b111c12c2867679c5555c00dcd4d6ab40efa01e3a65083bfb6c6f5c1ed3356d7141ec61894153b8ba7fb413bf1e990ed99ff6dee5da1b24fd83\n");
-	println!("PUBKEY (Bob's public key hash): 0x8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48\n");
-	println!("NEW UTXO HASH in UTXOStore onchain: 0xdbc75ab8ee9b83dcbcea4695f9c42754d94e92c3c397d63b1bc627c2a2ef94e6\n");
+	println!("OUTPOINT (Alice's UTXO): txid 0x990e8c5790fb32d6a2c7edee57d22eb8b63d0cf1772da4573b98c7171a61ca52, index 0\n");
+	println!("SIGSCRIPT (Alice Signature on a transaction where she spends 50 utxo on Bob): 0xa294927b9b4a255dc3739b3f4311defdb2dd7183d9396581507471784fe0fd6141aedacfd5f865070037c33cec0bc1e5c66c746fec33b34fef4c1bf36dc40589\n");
+	println!("SCRIPT (PayToPubkey with Bob's public key): 0x8eaf04151687736326c9fea17e25fc5287613693c912909cb226aa4794f26a48\n");
+	println!("NEW UTXO in UTXOStore onchain: txid 0xa0bee9a6c8b40f6208e9634c09e2e559261fec87170a5f2e8f8562d440777b6c, index 0\n");
 
 	GenesisConfig {
 		frame_system: Some(SystemConfig {
@@ -119,6 +145,8 @@ fn testnet_genesis(
 		}),
 		difficulty: Some(DifficultyAdjustmentConfig {
 			initial_difficulty: 4_000_000.into(),
+			// Anchors ASERT at the first block, if the runtime uses it
+			asert_anchor: Default::default(),
 		}),
 		utxo: Some(UtxoConfig {
 		  genesis_utxos: endowed_utxos
@@ -126,7 +154,7 @@ fn testnet_genesis(
 			.map(|x|
 				utxo::TransactionOutput {
 					value: 100 as utxo::Value,
-					pubkey: H256::from_slice(x.as_slice()),
+					script: Script::PayToPubkey(H256::from_slice(x.as_slice())),
 				}
 			)
 			.collect()
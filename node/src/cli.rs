@@ -1,4 +1,3 @@
-pub use sc_cli::Subcommand;
 use std::convert::TryInto;
 use structopt::StructOpt;
 
@@ -11,6 +10,16 @@ pub struct Cli {
 	pub run: RunCmd,
 }
 
+#[derive(Debug, StructOpt)]
+pub enum Subcommand {
+	#[structopt(flatten)]
+	Base(sc_cli::Subcommand),
+
+	/// The custom benchmark subcommmand benchmarking runtime pallets.
+	#[structopt(name = "benchmark", about = "Benchmark runtime pallets.")]
+	Benchmark(frame_benchmarking_cli::BenchmarkCmd),
+}
+
 #[derive(Debug, StructOpt)]
 pub struct RunCmd {
 	#[structopt(flatten)]
@@ -19,6 +28,23 @@ pub struct RunCmd {
 	/// Miner's SR25519 public key for block rewards
 	#[structopt(long, parse(try_from_str = parse_sr25519_public_key))]
 	pub sr25519_public_key: Option<sp_core::sr25519::Public>,
+
+	/// Number of threads mining on the CPU. 0 leaves mining to external miners.
+	#[structopt(long, value_name = "COUNT", default_value = "1")]
+	pub mining_threads: usize,
+
+	/// Number of nonces each mining thread tries before checking for a new block to mine on
+	#[structopt(long, value_name = "COUNT", default_value = "500")]
+	pub mining_rounds: u32,
+
+	/// Serve Stratum mining jobs to miners connecting on this address, e.g. 0.0.0.0:3333.
+	/// Requires `--validator`.
+	#[structopt(long, value_name = "ADDR")]
+	pub stratum: Option<std::net::SocketAddr>,
+
+	/// Difficulty of the shares Stratum miners submit, capped at the block difficulty
+	#[structopt(long, value_name = "DIFFICULTY", default_value = "1000")]
+	pub stratum_share_difficulty: u64,
 }
 
 fn parse_sr25519_public_key(i: &str) -> Result<sp_core::sr25519::Public, String> {
@@ -16,10 +16,13 @@
 
 use crate::service;
 use crate::chain_spec;
-use crate::cli::Cli;
+use crate::cli::{Cli, Subcommand};
 use sc_cli::{SubstrateCli, RuntimeVersion, Role, ChainSpec};
 use sc_service::PartialComponents;
 use crate::service::new_partial;
+use crate::mining::MiningConfig;
+use crate::stratum::StratumConfig;
+use utxo_runtime::opaque::Block;
 
 impl SubstrateCli for Cli {
 	fn impl_name() -> String {
@@ -71,7 +74,7 @@ pub fn run() -> sc_cli::Result<()> {
 	let default_sr25519_public_key = sp_core::sr25519::Public::from_raw([0; 32]);
 
 	match &cli.subcommand {
-		Some(subcommand) => {
+		Some(Subcommand::Base(subcommand)) => {
 			let runner = cli.create_runner(subcommand)?;
 			runner.run_subcommand(subcommand, |config| {
 				let PartialComponents { client, backend, task_manager, import_queue, .. }
@@ -79,12 +82,29 @@ pub fn run() -> sc_cli::Result<()> {
 				Ok((client, backend, import_queue, task_manager))
 			})
 		},
+		Some(Subcommand::Benchmark(cmd)) => {
+			if cfg!(feature = "runtime-benchmarks") {
+				let runner = cli.create_runner(cmd)?;
+				runner.sync_run(|config| cmd.run::<Block, service::Executor>(config))
+			} else {
+				Err("Benchmarking wasn't enabled when building the node. \
+				You can enable it with `--features runtime-benchmarks`.".into())
+			}
+		},
 		None => {
 			let sr25519_public_key = cli.run.sr25519_public_key.unwrap_or(default_sr25519_public_key);
+			let mining = MiningConfig {
+				threads: cli.run.mining_threads,
+				rounds: cli.run.mining_rounds,
+				stratum: cli.run.stratum.map(|listen| StratumConfig {
+					listen,
+					share_difficulty: cli.run.stratum_share_difficulty,
+				}),
+			};
 			let runner = cli.create_runner(&cli.run.base)?;
 			runner.run_node_until_exit(|config| match config.role {
 				Role::Light => service::new_light(config, sr25519_public_key),
-				_ => service::new_full(config, sr25519_public_key),
+				_ => service::new_full(config, sr25519_public_key, mining),
 			})
 		},
 	}
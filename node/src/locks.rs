@@ -0,0 +1,142 @@
+//! Wakes transactions waiting in the pool for their lock times.
+//!
+//! The runtime keeps a transaction whose locks have not expired in the future queue, requiring
+//! the pool tag of each pending lock. No transaction provides those tags, so after every new
+//! best block this task provides the tags of the locks that expired with it, which promotes the
+//! waiting transactions to the ready queue without validating them again.
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+use log::{debug, warn};
+use sc_client_api::BlockchainEvents;
+use sc_transaction_graph::{ChainApi, Pool};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::generic::BlockId;
+use utxo_runtime::{opaque::Block, utxo::LockTime, utxo_api::UtxoApi};
+
+/// Provide the pool tags of the locks that expire as the pool clock moves from `from` to `to`
+pub fn promote<A: ChainApi>(pool: &Pool<A>, from: (u64, u64), to: (u64, u64)) {
+	let tags = LockTime::expired_pool_tags(from, to);
+	if tags.is_empty() {
+		return;
+	}
+	match pool.validated_pool().prune_tags(tags) {
+		Ok(status) if !status.promoted.is_empty() =>
+			debug!(target: "txpool", "Promoted {} transactions whose locks expired", status.promoted.len()),
+		Ok(_) => {},
+		Err(e) => warn!(target: "txpool", "Promoting transactions whose locks expired failed: {:?}", e),
+	}
+}
+
+/// Promote the transactions in `pool` whose locks expire with each new best block of `client`
+pub async fn promote_unlocked<C, A>(client: Arc<C>, pool: Arc<Pool<A>>)
+where
+	C: ProvideRuntimeApi<Block> + BlockchainEvents<Block> + HeaderBackend<Block>,
+	C::Api: UtxoApi<Block>,
+	A: ChainApi<Block = Block>,
+{
+	let lock_clock = |hash| client.runtime_api().lock_clock(&BlockId::Hash(hash)).ok();
+
+	let mut imports = client.import_notification_stream();
+	let mut clock = lock_clock(client.info().best_hash);
+	while let Some(notification) = imports.next().await {
+		if !notification.is_new_best {
+			continue;
+		}
+		let now = match lock_clock(notification.hash) {
+			Some(now) => now,
+			None => continue,
+		};
+		// A reorg may move the clock back, but what expired has been provided already
+		clock = Some(match clock {
+			Some(last) => {
+				promote(&pool, last, now);
+				(last.0.max(now.0), last.1.max(now.1))
+			},
+			None => now,
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::{executor::block_on, future};
+	use parity_scale_codec::{Decode, Encode};
+	use sp_runtime::{
+		traits::{BlakeTwo256, Hash, NumberFor},
+		transaction_validity::{TransactionSource, TransactionValidity, ValidTransaction},
+		OpaqueExtrinsic,
+	};
+	use sp_transaction_pool::error::Error;
+
+	/// Validates extrinsics holding an encoded `LockTime` as waiting for that lock
+	struct LockedTransactions;
+
+	impl ChainApi for LockedTransactions {
+		type Block = Block;
+		type Error = Error;
+		type ValidationFuture = future::Ready<Result<TransactionValidity, Error>>;
+		type BodyFuture = future::Ready<Result<Option<Vec<OpaqueExtrinsic>>, Error>>;
+
+		fn validate_transaction(
+			&self,
+			_at: &BlockId<Block>,
+			_source: TransactionSource,
+			uxt: OpaqueExtrinsic,
+		) -> Self::ValidationFuture {
+			let bytes = Vec::<u8>::decode(&mut &uxt.encode()[..]).unwrap();
+			let lock = LockTime::decode(&mut &bytes[..]).unwrap();
+			future::ready(Ok(Ok(ValidTransaction {
+				requires: vec![lock.pool_tag()],
+				provides: vec![bytes],
+				..Default::default()
+			})))
+		}
+
+		fn block_id_to_number(&self, _at: &BlockId<Block>) -> Result<Option<NumberFor<Block>>, Error> {
+			Ok(Some(0))
+		}
+
+		fn block_id_to_hash(&self, _at: &BlockId<Block>) -> Result<Option<sp_core::H256>, Error> {
+			Ok(Some(Default::default()))
+		}
+
+		fn hash_and_length(&self, uxt: &OpaqueExtrinsic) -> (sp_core::H256, usize) {
+			(BlakeTwo256::hash_of(uxt), uxt.encode().len())
+		}
+
+		fn block_body(&self, _at: &BlockId<Block>) -> Self::BodyFuture {
+			future::ready(Ok(None))
+		}
+	}
+
+	fn submit_locked(pool: &Pool<LockedTransactions>, lock: LockTime) {
+		let uxt = OpaqueExtrinsic::from_bytes(&lock.encode().encode()).unwrap();
+		block_on(pool.submit_one(&BlockId::Number(0), TransactionSource::External, uxt)).unwrap();
+	}
+
+	fn ready_and_future(pool: &Pool<LockedTransactions>) -> (usize, usize) {
+		let status = pool.validated_pool().status();
+		(status.ready, status.future)
+	}
+
+	#[test]
+	fn locked_transactions_are_promoted_once_their_locks_expire() {
+		let pool = Pool::new(Default::default(), Arc::new(LockedTransactions));
+		submit_locked(&pool, LockTime::Block(5));
+		submit_locked(&pool, LockTime::Time(61_001));
+		assert_eq!(ready_and_future(&pool), (0, 2));
+
+		promote(&pool, (3, 60_000), (4, 61_000));
+		assert_eq!(ready_and_future(&pool), (0, 2));
+
+		promote(&pool, (4, 61_000), (5, 61_500));
+		assert_eq!(ready_and_future(&pool), (1, 1));
+
+		promote(&pool, (5, 61_500), (6, 62_000));
+		assert_eq!(ready_and_future(&pool), (2, 0));
+	}
+}
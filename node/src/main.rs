@@ -6,6 +6,10 @@ mod chain_spec;
 mod service;
 mod cli;
 mod command;
+mod locks;
+mod mining;
+mod rpc;
+mod stratum;
 
 fn main() -> sc_cli::Result<()> {
 	command::run()
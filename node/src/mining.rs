@@ -0,0 +1,672 @@
+//! Proof of work mining shared by the built-in miner and external ones.
+//!
+//! `sc_consensus_pow::start_mine` keeps the block it is mining to itself, so nothing outside the
+//! node can work on it. Instead a `MiningWorker` keeps a proposal on top of the best block,
+//! hands out its pre-hash and difficulty to any miner, and imports the block once one of them
+//! submits a seal for it.
+
+use std::{
+	any::Any,
+	borrow::Cow,
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+use futures::{FutureExt, Stream, StreamExt};
+use log::{debug, error, info, warn};
+use prometheus_endpoint::{
+	exponential_buckets, register, Counter, Gauge, Histogram, HistogramOpts, PrometheusError,
+	Registry, F64, U64,
+};
+use rand::Rng;
+use sc_consensus_pow::{Error, PowAlgorithm, PowIntermediate, INTERMEDIATE_KEY};
+use sha3pow::NonceSearch;
+use sp_consensus::{
+	import_queue::BoxBlockImport, BlockImportParams, BlockOrigin, CanAuthorWith, Environment,
+	Proposal, Proposer, RecordProof, SelectChain, SyncOracle,
+};
+use sp_consensus_pow::{Seal as RawSeal, POW_ENGINE_ID};
+use sp_core::{H256, U256};
+use sp_inherents::InherentDataProviders;
+use sp_runtime::{
+	generic::{BlockId, Digest, DigestItem},
+	traits::{Block as BlockT, Header as HeaderT},
+};
+use utxo_runtime::opaque::Block;
+
+use crate::stratum::StratumConfig;
+
+/// How often the worker checks for a new best block
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a proposal is mined on at least before it is rebuilt to pick up new transactions
+const REBUILD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the CPU miner reports its hashrate
+const HASHRATE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How a mining node mines
+#[derive(Clone, Debug)]
+pub struct MiningConfig {
+	/// Number of CPU miner threads. With none the node only builds blocks for external miners.
+	pub threads: usize,
+	/// Number of nonces a CPU miner thread tries between checks for new work
+	pub rounds: u32,
+	/// Where to serve work over Stratum, if anywhere
+	pub stratum: Option<StratumConfig>,
+}
+
+/// What a miner needs to know to search for a seal
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct MiningMetadata {
+	/// Hash of the block the proposal builds on
+	pub best_hash: H256,
+	/// Hash of the proposal's header without a seal, which is what the seal commits to
+	pub pre_hash: H256,
+	/// Difficulty the seal has to meet
+	pub difficulty: U256,
+}
+
+/// Block being mined
+struct MiningBuild<Transaction> {
+	metadata: MiningMetadata,
+	proposal: Proposal<Block, Transaction>,
+	built_at: Instant,
+	/// When mining on top of `metadata.best_hash` started, which earlier builds may have done
+	mining_since: Instant,
+}
+
+/// Prometheus metrics of the miners
+#[derive(Clone)]
+struct Metrics {
+	hashes: Counter<U64>,
+	solutions: Counter<U64>,
+	stale_seals: Counter<U64>,
+	difficulty: Gauge<F64>,
+	time_to_solve: Histogram,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			hashes: register(
+				Counter::new("mining_hashes_total", "Number of nonces tried by the CPU miner")?,
+				registry,
+			)?,
+			solutions: register(
+				Counter::new("mining_solutions_total", "Number of mined blocks imported")?,
+				registry,
+			)?,
+			stale_seals: register(
+				Counter::new("mining_stale_seals_total", "Number of seals submitted for blocks no longer being mined")?,
+				registry,
+			)?,
+			difficulty: register(
+				Gauge::new("mining_difficulty", "Difficulty of the block being mined")?,
+				registry,
+			)?,
+			time_to_solve: register(
+				Histogram::with_opts(HistogramOpts::new(
+					"mining_time_to_solve_seconds",
+					"Time from starting to mine on a block until sealing a block on top of it",
+				).buckets(exponential_buckets(1.0, 2.0, 12)?))?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// Reasons a submitted seal is not imported
+#[derive(Debug)]
+pub enum SubmitError {
+	/// The seal is for a block that is no longer being mined
+	Stale,
+	/// The seal does not meet the difficulty, or was not made for the block
+	InvalidSeal,
+	/// Verifying the seal or importing the sealed block failed
+	Consensus(Box<Error<Block>>),
+}
+
+impl std::fmt::Display for SubmitError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			SubmitError::Stale => write!(f, "Work is stale"),
+			SubmitError::InvalidSeal => write!(f, "Seal is invalid"),
+			SubmitError::Consensus(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+/// The block currently being mined, and the block import to hand it to once sealed
+pub struct MiningWorker<Algorithm, Transaction> {
+	build: Mutex<Option<MiningBuild<Transaction>>>,
+	block_import: Mutex<BoxBlockImport<Block, Transaction>>,
+	algorithm: Algorithm,
+	metrics: Option<Metrics>,
+}
+
+impl<Algorithm, Transaction> MiningWorker<Algorithm, Transaction> where
+	Algorithm: PowAlgorithm<Block, Difficulty = U256>,
+	Transaction: Send + 'static,
+{
+	/// The algorithm seals are found and verified with
+	pub fn algorithm(&self) -> &Algorithm {
+		&self.algorithm
+	}
+
+	/// Work on the block currently being mined, if any
+	pub fn metadata(&self) -> Option<MiningMetadata> {
+		self.build.lock().expect("mining worker lock poisoned").as_ref().map(|build| build.metadata.clone())
+	}
+
+	/// Seal the block with pre-hash `pre_hash` and import it. Returns the hash of the block.
+	pub fn submit(&self, pre_hash: H256, seal: RawSeal) -> Result<H256, SubmitError> {
+		// Verified without holding the build, as memory-hard seals take a while
+		let metadata = match self.metadata() {
+			Some(metadata) if metadata.pre_hash == pre_hash => metadata,
+			_ => return Err(self.stale()),
+		};
+		let valid = self.algorithm.verify(
+			&BlockId::Hash(metadata.best_hash),
+			&metadata.pre_hash,
+			None,
+			&seal,
+			metadata.difficulty,
+		).map_err(|e| SubmitError::Consensus(Box::new(e)))?;
+		if !valid {
+			return Err(SubmitError::InvalidSeal);
+		}
+
+		// Another seal may have been submitted for the block meanwhile, or it was rebuilt
+		let build = {
+			let mut build = self.build.lock().expect("mining worker lock poisoned");
+			if build.as_ref().map(|current| current.metadata.pre_hash) != Some(pre_hash) {
+				drop(build);
+				return Err(self.stale());
+			}
+			build.take().expect("checked above; qed")
+		};
+
+		let MiningBuild { metadata, proposal, mining_since, .. } = build;
+		let (header, body) = proposal.block.deconstruct();
+		let (hash, seal) = {
+			let mut header = header.clone();
+			header.digest_mut().push(DigestItem::Seal(POW_ENGINE_ID, seal));
+			let hash = header.hash();
+			let seal = header.digest_mut().pop()
+				.expect("Pushed one seal above; length greater than zero; qed");
+			(hash, seal)
+		};
+
+		let intermediate = PowIntermediate::<U256> {
+			difficulty: Some(metadata.difficulty),
+		};
+
+		let mut import_block = BlockImportParams::new(BlockOrigin::Own, header);
+		import_block.post_digests.push(seal);
+		import_block.body = Some(body);
+		import_block.storage_changes = Some(proposal.storage_changes);
+		import_block.intermediates.insert(
+			Cow::from(INTERMEDIATE_KEY),
+			Box::new(intermediate) as Box<dyn Any>
+		);
+		import_block.post_hash = Some(hash);
+
+		self.block_import.lock().expect("mining worker lock poisoned")
+			.import_block(import_block, HashMap::default())
+			.map_err(|e| SubmitError::Consensus(Box::new(Error::BlockBuiltError(metadata.best_hash, e))))?;
+
+		if let Some(metrics) = &self.metrics {
+			metrics.solutions.inc();
+			metrics.time_to_solve.observe(mining_since.elapsed().as_secs_f64());
+		}
+		info!("✅ Successfully mined block on top of: {}", metadata.best_hash);
+		Ok(hash)
+	}
+
+	/// Count a seal submitted for a block that is no longer being mined
+	fn stale(&self) -> SubmitError {
+		if let Some(metrics) = &self.metrics {
+			metrics.stale_seals.inc();
+		}
+		SubmitError::Stale
+	}
+
+	/// Best block the current build is on top of, and whether it was built long enough ago
+	/// to be rebuilt for new transactions
+	fn current_build(&self) -> Option<(H256, bool)> {
+		self.build.lock().expect("mining worker lock poisoned")
+			.as_ref()
+			.map(|build| (build.metadata.best_hash, build.built_at.elapsed() >= REBUILD_INTERVAL))
+	}
+
+	/// When mining on top of `best_hash` started, if the current build is on top of it
+	fn mining_since(&self, best_hash: H256) -> Option<Instant> {
+		self.build.lock().expect("mining worker lock poisoned")
+			.as_ref()
+			.filter(|build| build.metadata.best_hash == best_hash)
+			.map(|build| build.mining_since)
+	}
+
+	fn set_build(&self, build: Option<MiningBuild<Transaction>>) {
+		*self.build.lock().expect("mining worker lock poisoned") = build;
+	}
+}
+
+/// Start a background thread that keeps a `MiningWorker` supplied with a proposal on top of
+/// the best block, and return the worker. Nothing is mined until a miner works on it.
+/// The proposal is rebuilt on a new best block, or once it has been mined on for
+/// `REBUILD_INTERVAL` if `transactions` announced new ones meanwhile.
+/// Mining metrics are registered with `registry`, if given.
+#[allow(clippy::too_many_arguments)]
+pub fn start_mining_worker<Algorithm, E, SO, S, TS, CAW>(
+	block_import: BoxBlockImport<Block, <E::Proposer as Proposer<Block>>::Transaction>,
+	algorithm: Algorithm,
+	mut env: E,
+	mut sync_oracle: SO,
+	build_time: Duration,
+	select_chain: S,
+	mut transactions: TS,
+	inherent_data_providers: InherentDataProviders,
+	can_author_with: CAW,
+	registry: Option<&Registry>,
+) -> Arc<MiningWorker<Algorithm, <E::Proposer as Proposer<Block>>::Transaction>> where
+	Algorithm: PowAlgorithm<Block, Difficulty = U256> + Send + Sync + 'static,
+	E: Environment<Block> + Send + Sync + 'static,
+	E::Error: std::fmt::Debug,
+	<E::Proposer as Proposer<Block>>::Transaction: Send + 'static,
+	SO: SyncOracle + Send + Sync + 'static,
+	S: SelectChain<Block> + 'static,
+	TS: Stream + Unpin + Send + 'static,
+	CAW: CanAuthorWith<Block> + Send + 'static,
+{
+	let worker = Arc::new(MiningWorker {
+		build: Mutex::new(None),
+		block_import: Mutex::new(block_import),
+		algorithm,
+		metrics: registry.and_then(|registry|
+			Metrics::register(registry)
+				.map_err(|err| warn!("Failed to register mining prometheus metrics: {}", err))
+				.ok()
+		),
+	});
+
+	let building = worker.clone();
+	thread::spawn(move || {
+		loop {
+			if let Err(e) = build_loop(
+				&building,
+				&mut env,
+				&mut sync_oracle,
+				build_time,
+				&select_chain,
+				&mut transactions,
+				&inherent_data_providers,
+				&can_author_with,
+			) {
+				error!("Building block to mine failed with {:?}. Sleep for 1 second before restarting...", e);
+			}
+			thread::sleep(Duration::from_secs(1));
+		}
+	});
+
+	worker
+}
+
+/// Mine on the work of `worker` on `threads` background threads, each searching its own part
+/// of the nonce space and trying `rounds` nonces between checks for new work. Their combined
+/// hashrate is logged every `HASHRATE_INTERVAL`.
+pub fn start_cpu_miner<Algorithm, Transaction>(
+	worker: Arc<MiningWorker<Algorithm, Transaction>>,
+	threads: usize,
+	rounds: u32,
+) where
+	Algorithm: PowAlgorithm<Block, Difficulty = U256> + NonceSearch<Block> + Send + Sync + 'static,
+	Transaction: Send + 'static,
+{
+	if threads == 0 {
+		return
+	}
+
+	let hashes = Arc::new(AtomicU64::new(0));
+	let segment = U256::max_value() / threads;
+	for index in 0..threads {
+		let worker = worker.clone();
+		let hashes = hashes.clone();
+		let segment_start = segment * index;
+		thread::spawn(move || {
+			let mut rng = rand::thread_rng();
+			let mut pre_hash = None;
+			let mut nonce = segment_start;
+			loop {
+				let metadata = match worker.metadata() {
+					Some(metadata) => metadata,
+					None => {
+						thread::sleep(POLL_INTERVAL);
+						continue
+					}
+				};
+
+				// Start somewhere random in the segment, so miners sharing a reward key do not
+				// repeat each other's work
+				if pre_hash != Some(metadata.pre_hash) {
+					pre_hash = Some(metadata.pre_hash);
+					nonce = segment_start + U256::from(rng.gen::<u64>());
+				}
+
+				let first = nonce;
+				let seal = worker.algorithm().search(
+					&metadata.best_hash,
+					&metadata.pre_hash,
+					metadata.difficulty,
+					&mut nonce,
+					rounds,
+				);
+				let tried = nonce.overflowing_sub(first).0.low_u64();
+				hashes.fetch_add(tried, Ordering::Relaxed);
+				if let Some(metrics) = &worker.metrics {
+					metrics.hashes.inc_by(tried);
+				}
+
+				match seal {
+					Ok(Some(seal)) => match worker.submit(metadata.pre_hash, seal) {
+						// Another thread or miner sealed the block first
+						Ok(_) | Err(SubmitError::Stale) => (),
+						Err(e) => warn!("Submitting mined seal failed: {}", e),
+					},
+					Ok(None) => (),
+					Err(e) => {
+						error!("Mining failed with {:?}. Sleep for 1 second before restarting...", e);
+						thread::sleep(Duration::from_secs(1));
+					}
+				}
+			}
+		});
+	}
+
+	thread::spawn(move || {
+		loop {
+			thread::sleep(HASHRATE_INTERVAL);
+			let hashes = hashes.swap(0, Ordering::Relaxed);
+			info!("⛏  Mining at {} H/s on {} threads", hashes / HASHRATE_INTERVAL.as_secs(), threads);
+		}
+	});
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_loop<Algorithm, E, SO, S, TS, CAW>(
+	worker: &MiningWorker<Algorithm, <E::Proposer as Proposer<Block>>::Transaction>,
+	env: &mut E,
+	sync_oracle: &mut SO,
+	build_time: Duration,
+	select_chain: &S,
+	transactions: &mut TS,
+	inherent_data_providers: &InherentDataProviders,
+	can_author_with: &CAW,
+) -> Result<(), Error<Block>> where
+	Algorithm: PowAlgorithm<Block, Difficulty = U256>,
+	E: Environment<Block>,
+	E::Error: std::fmt::Debug,
+	<E::Proposer as Proposer<Block>>::Transaction: Send + 'static,
+	SO: SyncOracle,
+	S: SelectChain<Block>,
+	TS: Stream + Unpin,
+	CAW: CanAuthorWith<Block>,
+{
+	let mut new_transactions = false;
+	loop {
+		if sync_oracle.is_major_syncing() {
+			debug!(target: "pow", "Skipping proposal due to sync.");
+			worker.set_build(None);
+			thread::sleep(Duration::from_secs(1));
+			continue
+		}
+
+		while let Some(Some(_)) = transactions.next().now_or_never() {
+			new_transactions = true;
+		}
+
+		let best_header = select_chain.best_chain().map_err(Error::BestHeaderSelectChain)?;
+		let best_hash = best_header.hash();
+		match worker.current_build() {
+			Some((built_on, rebuildable)) if built_on == best_hash && !(new_transactions && rebuildable) => {
+				thread::sleep(POLL_INTERVAL);
+				continue
+			},
+			_ => (),
+		}
+
+		if let Err(err) = can_author_with.can_author_with(&BlockId::Hash(best_hash)) {
+			warn!(
+				target: "pow",
+				"Skipping proposal `can_author_with` returned: {} \
+				Probably a node update is required!",
+				err,
+			);
+			worker.set_build(None);
+			thread::sleep(Duration::from_secs(1));
+			continue
+		}
+
+		let proposer = futures::executor::block_on(env.init(&best_header))
+			.map_err(|e| Error::Environment(format!("{:?}", e)))?;
+
+		let inherent_data = inherent_data_providers
+			.create_inherent_data().map_err(Error::CreateInherents)?;
+		new_transactions = false;
+		let proposal = futures::executor::block_on(proposer.propose(
+			inherent_data,
+			Digest::default(),
+			build_time,
+			RecordProof::No,
+		)).map_err(|e| Error::BlockProposingError(format!("{:?}", e)))?;
+
+		let metadata = MiningMetadata {
+			best_hash,
+			pre_hash: proposal.block.header().hash(),
+			difficulty: worker.algorithm.difficulty(best_hash)?,
+		};
+		if let Some(metrics) = &worker.metrics {
+			metrics.difficulty.set(u256_to_f64(metadata.difficulty));
+		}
+		let mining_since = worker.mining_since(best_hash).unwrap_or_else(Instant::now);
+		worker.set_build(Some(MiningBuild { metadata, proposal, built_at: Instant::now(), mining_since }));
+	}
+}
+
+/// `value` as the nearest float, for metrics
+fn u256_to_f64(value: U256) -> f64 {
+	value.0.iter().rev().fold(0.0, |float, &limb| float * 18_446_744_073_709_551_616.0 + limb as f64)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use parity_scale_codec::{Decode, Encode};
+	use sha3pow::{hash_meets_difficulty, Compute, Seal, Sha3Algorithm};
+	use sp_consensus::{BlockCheckParams, BlockImport, Error as ConsensusError, ImportResult};
+
+	/// Difficulty of the blocks the test worker mines
+	pub const DIFFICULTY: u64 = 64;
+
+	/// SHA3-256 PoW at `DIFFICULTY`, without a client
+	pub struct TestAlgorithm(Sha3Algorithm<()>);
+
+	impl PowAlgorithm<Block> for TestAlgorithm {
+		type Difficulty = U256;
+
+		fn difficulty(&self, _parent: H256) -> Result<U256, Error<Block>> {
+			Ok(DIFFICULTY.into())
+		}
+
+		fn verify(
+			&self,
+			_parent: &BlockId<Block>,
+			pre_hash: &H256,
+			_pre_digest: Option<&[u8]>,
+			seal: &RawSeal,
+			difficulty: U256,
+		) -> Result<bool, Error<Block>> {
+			let seal = match Seal::decode(&mut &seal[..]) {
+				Ok(seal) => seal,
+				Err(_) => return Ok(false),
+			};
+			let compute = Compute { difficulty, pre_hash: *pre_hash, nonce: seal.nonce };
+			Ok(hash_meets_difficulty(&seal.work, difficulty) && compute.compute() == seal)
+		}
+
+		fn mine(
+			&self,
+			_parent: &BlockId<Block>,
+			_pre_hash: &H256,
+			_pre_digest: Option<&[u8]>,
+			_difficulty: U256,
+			_round: u32,
+		) -> Result<Option<RawSeal>, Error<Block>> {
+			Ok(None)
+		}
+	}
+
+	impl NonceSearch<Block> for TestAlgorithm {
+		fn seal(&self, parent: &H256, pre_hash: &H256, difficulty: U256, nonce: H256) -> Result<Seal, Error<Block>> {
+			self.0.seal(parent, pre_hash, difficulty, nonce)
+		}
+
+		fn search(
+			&self,
+			parent: &H256,
+			pre_hash: &H256,
+			difficulty: U256,
+			nonce: &mut U256,
+			rounds: u32,
+		) -> Result<Option<RawSeal>, Error<Block>> {
+			self.0.search(parent, pre_hash, difficulty, nonce, rounds)
+		}
+	}
+
+	/// Block import that only counts the blocks it is given
+	struct CountingImport(Arc<AtomicU64>);
+
+	impl BlockImport<Block> for CountingImport {
+		type Error = ConsensusError;
+		type Transaction = ();
+
+		fn check_block(&mut self, _block: BlockCheckParams<Block>) -> Result<ImportResult, Self::Error> {
+			Ok(ImportResult::imported(false))
+		}
+
+		fn import_block(
+			&mut self,
+			_block: BlockImportParams<Block, ()>,
+			_cache: HashMap<[u8; 4], Vec<u8>>,
+		) -> Result<ImportResult, Self::Error> {
+			self.0.fetch_add(1, Ordering::Relaxed);
+			Ok(ImportResult::imported(false))
+		}
+	}
+
+	/// A worker mining an empty block at `DIFFICULTY`, and the number of blocks it imported
+	pub fn test_worker() -> (Arc<MiningWorker<TestAlgorithm, ()>>, Arc<AtomicU64>) {
+		let imported = Arc::new(AtomicU64::new(0));
+		let worker = Arc::new(MiningWorker {
+			build: Mutex::new(None),
+			block_import: Mutex::new(Box::new(CountingImport(imported.clone()))),
+			algorithm: TestAlgorithm(Sha3Algorithm::new(Arc::new(()))),
+			metrics: None,
+		});
+		rebuild(&worker, H256::repeat_byte(1), 0);
+		(worker, imported)
+	}
+
+	/// Give `worker` a new empty block on top of `parent`, told apart from other blocks on the
+	/// same parent by `salt`. Returns its pre-hash.
+	pub fn rebuild(worker: &MiningWorker<TestAlgorithm, ()>, parent: H256, salt: u8) -> H256 {
+		let header = <Block as BlockT>::Header::new(
+			1,
+			Default::default(),
+			H256::repeat_byte(salt),
+			parent,
+			Default::default(),
+		);
+		let pre_hash = header.hash();
+		worker.set_build(Some(MiningBuild {
+			metadata: MiningMetadata { best_hash: parent, pre_hash, difficulty: DIFFICULTY.into() },
+			proposal: Proposal {
+				block: Block::new(header, Vec::new()),
+				proof: None,
+				storage_changes: Default::default(),
+			},
+			built_at: Instant::now(),
+			mining_since: Instant::now(),
+		}));
+		pre_hash
+	}
+
+	/// The first nonce starting with `prefix` whose work for the block with `pre_hash`
+	/// satisfies `accept`
+	pub fn find_nonce(prefix: &[u8], pre_hash: H256, accept: impl Fn(&H256) -> bool) -> H256 {
+		(0u64..)
+			.map(|counter| {
+				let mut nonce = H256::zero();
+				nonce[..prefix.len()].copy_from_slice(prefix);
+				nonce[24..].copy_from_slice(&counter.to_be_bytes());
+				nonce
+			})
+			.find(|&nonce| accept(&Compute { difficulty: DIFFICULTY.into(), pre_hash, nonce }.compute().work))
+			.expect("Some nonce is accepted")
+	}
+
+	/// Encoded seal of `nonce` for the block with `pre_hash`
+	pub fn seal(pre_hash: H256, nonce: H256) -> RawSeal {
+		Compute { difficulty: DIFFICULTY.into(), pre_hash, nonce }.compute().encode()
+	}
+
+	/// Whether `work` meets `DIFFICULTY`
+	pub fn meets_difficulty(work: &H256) -> bool {
+		hash_meets_difficulty(work, DIFFICULTY.into())
+	}
+
+	#[test]
+	fn submit_rejects_stale_pre_hash() {
+		let (worker, imported) = test_worker();
+		let stale = worker.metadata().unwrap().pre_hash;
+		let current = rebuild(&worker, H256::repeat_byte(2), 0);
+
+		let nonce = find_nonce(&[], stale, meets_difficulty);
+		assert!(matches!(worker.submit(stale, seal(stale, nonce)), Err(SubmitError::Stale)));
+		assert_eq!(worker.metadata().unwrap().pre_hash, current);
+
+		let nonce = find_nonce(&[], current, meets_difficulty);
+		assert!(worker.submit(current, seal(current, nonce)).is_ok());
+		assert_eq!(imported.load(Ordering::Relaxed), 1);
+
+		// Once imported, the block is no longer being mined
+		assert!(worker.metadata().is_none());
+		assert!(matches!(worker.submit(current, seal(current, nonce)), Err(SubmitError::Stale)));
+		assert_eq!(imported.load(Ordering::Relaxed), 1);
+	}
+
+	#[test]
+	fn submit_rejects_invalid_seal() {
+		let (worker, imported) = test_worker();
+		let pre_hash = worker.metadata().unwrap().pre_hash;
+
+		let too_easy = find_nonce(&[], pre_hash, |work| !meets_difficulty(work));
+		assert!(matches!(worker.submit(pre_hash, seal(pre_hash, too_easy)), Err(SubmitError::InvalidSeal)));
+		assert!(matches!(worker.submit(pre_hash, vec![1, 2, 3]), Err(SubmitError::InvalidSeal)));
+
+		// A seal made for another block does not verify either
+		let other = H256::repeat_byte(9);
+		let nonce = find_nonce(&[], other, meets_difficulty);
+		assert!(matches!(worker.submit(pre_hash, seal(other, nonce)), Err(SubmitError::InvalidSeal)));
+
+		// Nothing was imported, and the block is still being mined
+		assert_eq!(imported.load(Ordering::Relaxed), 0);
+		assert_eq!(worker.metadata().unwrap().pre_hash, pre_hash);
+	}
+}
@@ -0,0 +1,603 @@
+//! UTXO specific RPCs of the node, in the `utxo_*` namespace. They are thin wrappers
+//! over the `UtxoApi` runtime API, evaluated at the best block unless a block hash is given.
+//! Transactions are passed around SCALE encoded, as hex. The `mining_*` RPCs are in `./rpc/mining.rs`,
+//! and the `pow_*` RPCs in `./rpc/pow.rs`.
+
+use std::{pin::Pin, sync::Arc};
+
+use futures::{FutureExt, TryFutureExt};
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use parity_scale_codec::{Decode, Encode};
+use sc_client_api::light::{future_header, Fetcher, RemoteBlockchain, RemoteCallRequest};
+use sc_consensus_pow::PowAlgorithm;
+use sc_rpc::DenyUnsafe;
+use serde::{Deserialize, Serialize};
+use sha3pow::NonceSearch;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus_pow::DifficultyApi;
+use sp_core::{Bytes, H256, U256};
+use sp_runtime::{
+	generic::BlockId,
+	transaction_validity::{TransactionSource, ValidTransaction},
+	DispatchError,
+	OpaqueExtrinsic,
+};
+use sp_transaction_pool::{error::IntoPoolError, TransactionPool};
+use utxo_runtime::{
+	difficulty::DifficultyAndTimestamp,
+	difficulty_api::DifficultyHistoryApi,
+	opaque::Block,
+	utxo::{self, OutPoint, Transaction, TransactionOutput, Value},
+	utxo_api::UtxoApi,
+	Call, UncheckedExtrinsic, Utxo as UtxoModule, Weight,
+};
+
+use crate::mining::MiningWorker;
+
+mod mining;
+mod pow;
+
+pub use mining::{Mining, MiningRpc, Work};
+pub use pow::{NetworkStats, PastBlock, Pow, PowRpc};
+
+/// Number of outputs `utxo_listUnspent` returns if no count is given
+const DEFAULT_PAGE_SIZE: u32 = 100;
+
+/// Upper bound on the number of outputs `utxo_listUnspent` returns at once
+const MAX_PAGE_SIZE: u32 = 1000;
+
+/// Boxed future of an RPC result
+pub type FutureResult<T> = jsonrpc_core::BoxFuture<T>;
+
+/// Error codes of the UTXO RPCs
+const RUNTIME_ERROR: i64 = 1;
+const INVALID_PARAMS: i64 = 2;
+const INVALID_TRANSACTION: i64 = 3;
+const POOL_ERROR: i64 = 4;
+
+/// An unspent output together with the outpoint that refers to it
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UnspentOutput {
+	pub outpoint: OutPoint,
+	pub output: TransactionOutput,
+}
+
+/// Outcome of checking a valid transaction against the UTXO set
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Validity {
+	/// Id of the transaction, the first part of the outpoints of its outputs
+	pub txid: H256,
+	/// Value left to the block author, zero while inputs are missing
+	pub fee: Value,
+	/// Tags of the missing inputs and unexpired locks the transaction waits for in the pool.
+	/// It can only be included once this is empty.
+	pub requires: Vec<Bytes>,
+	/// Tags of the outputs the transaction creates
+	pub provides: Vec<Bytes>,
+}
+
+/// What a transaction costs its sender
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeEstimate {
+	/// Weight charged for dispatching the transaction
+	pub weight: Weight,
+	/// Value of the inputs not spent by the outputs, which goes to the block author
+	pub fee: Value,
+}
+
+#[rpc]
+pub trait UtxoRpc<BlockHash> {
+	/// The unspent output at `outpoint`, if any
+	#[rpc(name = "utxo_getUtxo")]
+	fn get_utxo(&self, outpoint: OutPoint, at: Option<BlockHash>) -> FutureResult<Option<TransactionOutput>>;
+
+	/// Unspent outputs whose script names `owner`, in outpoint order. Returns at most `count`
+	/// of them, starting after the outpoint `after`. Pass the last outpoint of a page as
+	/// `after` to get the next page.
+	#[rpc(name = "utxo_listUnspent")]
+	fn list_unspent(
+		&self,
+		owner: H256,
+		after: Option<OutPoint>,
+		count: Option<u32>,
+		at: Option<BlockHash>,
+	) -> FutureResult<Vec<UnspentOutput>>;
+
+	/// Decode a transaction
+	#[rpc(name = "utxo_decodeTransaction")]
+	fn decode_transaction(&self, transaction: Bytes) -> Result<Transaction>;
+
+	/// Check a signed transaction against the UTXO set. An invalid transaction is reported
+	/// as an error whose data is the code of the failed check, as in
+	/// `InvalidTransaction::Custom`.
+	#[rpc(name = "utxo_validateTransaction")]
+	fn validate_transaction(&self, transaction: Bytes, at: Option<BlockHash>) -> FutureResult<Validity>;
+
+	/// The payload every input of `transaction` has to sign, which leaves out the sigscripts
+	#[rpc(name = "utxo_signingPayload")]
+	fn signing_payload(&self, transaction: Bytes) -> FutureResult<Bytes>;
+
+	/// Weight and fee of `transaction`, which does not need to be signed yet
+	#[rpc(name = "utxo_estimateFee")]
+	fn estimate_fee(&self, transaction: Bytes, at: Option<BlockHash>) -> FutureResult<FeeEstimate>;
+
+	/// Submit a signed transaction to the transaction pool and return its id
+	#[rpc(name = "utxo_submitTransaction")]
+	fn submit_transaction(&self, transaction: Bytes) -> FutureResult<H256>;
+}
+
+/// Boxed future of the result of a runtime API call
+pub type RuntimeResult<T> = Pin<Box<dyn std::future::Future<Output = Result<T>> + Send>>;
+
+/// Access to the chain and runtime of the node. A full node calls its own runtime,
+/// a light node asks a full node to do so. The runtime API functions are called at block `at`.
+pub trait RuntimeAccess: Send + Sync + 'static {
+	/// Hash of the best block
+	fn best_hash(&self) -> H256;
+
+	/// `UtxoApi::get_utxo`
+	fn get_utxo(&self, at: H256, outpoint: OutPoint) -> RuntimeResult<Option<TransactionOutput>>;
+
+	/// `UtxoApi::list_unspent`
+	fn list_unspent(
+		&self,
+		at: H256,
+		owner: H256,
+		after: Option<OutPoint>,
+		count: u32,
+	) -> RuntimeResult<Vec<(OutPoint, TransactionOutput)>>;
+
+	/// `UtxoApi::validate`
+	fn validate(
+		&self,
+		at: H256,
+		transaction: Transaction,
+	) -> RuntimeResult<std::result::Result<ValidTransaction, DispatchError>>;
+
+	/// `UtxoApi::simple_transaction_bytes`
+	fn simple_transaction_bytes(&self, at: H256, transaction: Transaction) -> RuntimeResult<Vec<u8>>;
+
+	/// `UtxoApi::fee`
+	fn fee(&self, at: H256, transaction: Transaction) -> RuntimeResult<Option<Value>>;
+
+	/// `UtxoApi::spend_weight`
+	fn spend_weight(&self, at: H256, transaction: Transaction) -> RuntimeResult<Weight>;
+
+	/// `DifficultyApi::difficulty`
+	fn difficulty(&self, at: H256) -> RuntimeResult<U256>;
+
+	/// `DifficultyHistoryApi::past_difficulties_and_timestamps`
+	fn past_difficulties_and_timestamps(&self, at: H256) -> RuntimeResult<Vec<Option<DifficultyAndTimestamp<u64>>>>;
+
+	/// `DifficultyHistoryApi::average_block_time`
+	fn average_block_time(&self, at: H256) -> RuntimeResult<Option<u64>>;
+
+	/// `DifficultyHistoryApi::network_hashrate`
+	fn network_hashrate(&self, at: H256) -> RuntimeResult<Option<U256>>;
+}
+
+/// `RuntimeAccess` of a full node
+pub struct FullAccess<C> {
+	client: Arc<C>,
+}
+
+/// Result of the runtime API function `method` of a full node
+fn full_result<T, E: std::fmt::Debug>(method: &'static str, result: std::result::Result<T, E>) -> RuntimeResult<T>
+where
+	T: Send + 'static,
+{
+	Box::pin(futures::future::ready(result.map_err(|e| runtime_error(method, e))))
+}
+
+impl<C> RuntimeAccess for FullAccess<C>
+where
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	C::Api: UtxoApi<Block> + DifficultyApi<Block, U256> + DifficultyHistoryApi<Block>,
+{
+	fn best_hash(&self) -> H256 {
+		self.client.info().best_hash
+	}
+
+	fn get_utxo(&self, at: H256, outpoint: OutPoint) -> RuntimeResult<Option<TransactionOutput>> {
+		full_result("UtxoApi_get_utxo", self.client.runtime_api().get_utxo(&BlockId::hash(at), outpoint))
+	}
+
+	fn list_unspent(
+		&self,
+		at: H256,
+		owner: H256,
+		after: Option<OutPoint>,
+		count: u32,
+	) -> RuntimeResult<Vec<(OutPoint, TransactionOutput)>> {
+		full_result(
+			"UtxoApi_list_unspent",
+			self.client.runtime_api().list_unspent(&BlockId::hash(at), owner, after, count),
+		)
+	}
+
+	fn validate(
+		&self,
+		at: H256,
+		transaction: Transaction,
+	) -> RuntimeResult<std::result::Result<ValidTransaction, DispatchError>> {
+		full_result("UtxoApi_validate", self.client.runtime_api().validate(&BlockId::hash(at), transaction))
+	}
+
+	fn simple_transaction_bytes(&self, at: H256, transaction: Transaction) -> RuntimeResult<Vec<u8>> {
+		full_result(
+			"UtxoApi_simple_transaction_bytes",
+			self.client.runtime_api().simple_transaction_bytes(&BlockId::hash(at), transaction),
+		)
+	}
+
+	fn fee(&self, at: H256, transaction: Transaction) -> RuntimeResult<Option<Value>> {
+		full_result("UtxoApi_fee", self.client.runtime_api().fee(&BlockId::hash(at), transaction))
+	}
+
+	fn spend_weight(&self, at: H256, transaction: Transaction) -> RuntimeResult<Weight> {
+		full_result("UtxoApi_spend_weight", self.client.runtime_api().spend_weight(&BlockId::hash(at), transaction))
+	}
+
+	fn difficulty(&self, at: H256) -> RuntimeResult<U256> {
+		full_result("DifficultyApi_difficulty", self.client.runtime_api().difficulty(&BlockId::hash(at)))
+	}
+
+	fn past_difficulties_and_timestamps(&self, at: H256) -> RuntimeResult<Vec<Option<DifficultyAndTimestamp<u64>>>> {
+		full_result(
+			"DifficultyHistoryApi_past_difficulties_and_timestamps",
+			self.client.runtime_api().past_difficulties_and_timestamps(&BlockId::hash(at)),
+		)
+	}
+
+	fn average_block_time(&self, at: H256) -> RuntimeResult<Option<u64>> {
+		full_result(
+			"DifficultyHistoryApi_average_block_time",
+			self.client.runtime_api().average_block_time(&BlockId::hash(at)),
+		)
+	}
+
+	fn network_hashrate(&self, at: H256) -> RuntimeResult<Option<U256>> {
+		full_result(
+			"DifficultyHistoryApi_network_hashrate",
+			self.client.runtime_api().network_hashrate(&BlockId::hash(at)),
+		)
+	}
+}
+
+/// `RuntimeAccess` of a light node
+pub struct LightAccess<C, F> {
+	client: Arc<C>,
+	remote_blockchain: Arc<dyn RemoteBlockchain<Block>>,
+	fetcher: Arc<F>,
+}
+
+impl<C, F> LightAccess<C, F>
+where
+	C: HeaderBackend<Block> + Send + Sync + 'static,
+	F: Fetcher<Block> + 'static,
+{
+	/// Ask a full node to call the runtime API function `method` with `args` at block `at`
+	fn remote_call<T: Decode + Send + 'static, A: Encode>(
+		&self,
+		at: H256,
+		method: &'static str,
+		args: &A,
+	) -> RuntimeResult<T> {
+		let header = future_header(&*self.remote_blockchain, &*self.fetcher, BlockId::hash(at));
+		let fetcher = self.fetcher.clone();
+		let call_data = args.encode();
+
+		Box::pin(async move {
+			let header = header.await
+				.map_err(|e| runtime_error(method, e))?
+				.ok_or_else(|| runtime_error(method, format!("Unknown block {}", at)))?;
+			let output = fetcher.remote_call(RemoteCallRequest {
+				block: at,
+				header,
+				method: method.into(),
+				call_data,
+				retry_count: None,
+			}).await.map_err(|e| runtime_error(method, e))?;
+			T::decode(&mut &output[..]).map_err(|e| runtime_error(method, e))
+		})
+	}
+}
+
+impl<C, F> RuntimeAccess for LightAccess<C, F>
+where
+	C: HeaderBackend<Block> + Send + Sync + 'static,
+	F: Fetcher<Block> + 'static,
+{
+	fn best_hash(&self) -> H256 {
+		self.client.info().best_hash
+	}
+
+	fn get_utxo(&self, at: H256, outpoint: OutPoint) -> RuntimeResult<Option<TransactionOutput>> {
+		self.remote_call(at, "UtxoApi_get_utxo", &outpoint)
+	}
+
+	fn list_unspent(
+		&self,
+		at: H256,
+		owner: H256,
+		after: Option<OutPoint>,
+		count: u32,
+	) -> RuntimeResult<Vec<(OutPoint, TransactionOutput)>> {
+		self.remote_call(at, "UtxoApi_list_unspent", &(owner, after, count))
+	}
+
+	fn validate(
+		&self,
+		at: H256,
+		transaction: Transaction,
+	) -> RuntimeResult<std::result::Result<ValidTransaction, DispatchError>> {
+		self.remote_call(at, "UtxoApi_validate", &transaction)
+	}
+
+	fn simple_transaction_bytes(&self, at: H256, transaction: Transaction) -> RuntimeResult<Vec<u8>> {
+		self.remote_call(at, "UtxoApi_simple_transaction_bytes", &transaction)
+	}
+
+	fn fee(&self, at: H256, transaction: Transaction) -> RuntimeResult<Option<Value>> {
+		self.remote_call(at, "UtxoApi_fee", &transaction)
+	}
+
+	fn spend_weight(&self, at: H256, transaction: Transaction) -> RuntimeResult<Weight> {
+		self.remote_call(at, "UtxoApi_spend_weight", &transaction)
+	}
+
+	fn difficulty(&self, at: H256) -> RuntimeResult<U256> {
+		self.remote_call(at, "DifficultyApi_difficulty", &())
+	}
+
+	fn past_difficulties_and_timestamps(&self, at: H256) -> RuntimeResult<Vec<Option<DifficultyAndTimestamp<u64>>>> {
+		self.remote_call(at, "DifficultyHistoryApi_past_difficulties_and_timestamps", &())
+	}
+
+	fn average_block_time(&self, at: H256) -> RuntimeResult<Option<u64>> {
+		self.remote_call(at, "DifficultyHistoryApi_average_block_time", &())
+	}
+
+	fn network_hashrate(&self, at: H256) -> RuntimeResult<Option<U256>> {
+		self.remote_call(at, "DifficultyHistoryApi_network_hashrate", &())
+	}
+}
+
+/// Implements `UtxoRpc` on top of a `RuntimeAccess` and a transaction pool
+pub struct Utxo<R, P> {
+	runtime: Arc<R>,
+	pool: Arc<P>,
+}
+
+impl<R: RuntimeAccess, P> Utxo<R, P> {
+	pub fn new(runtime: R, pool: Arc<P>) -> Self {
+		Utxo { runtime: Arc::new(runtime), pool }
+	}
+
+	/// Block `at`, or the best block
+	fn at(&self, at: Option<H256>) -> H256 {
+		at.unwrap_or_else(|| self.runtime.best_hash())
+	}
+}
+
+impl<R, P> UtxoRpc<H256> for Utxo<R, P>
+where
+	R: RuntimeAccess,
+	P: TransactionPool<Block = Block> + 'static,
+{
+	fn get_utxo(&self, outpoint: OutPoint, at: Option<H256>) -> FutureResult<Option<TransactionOutput>> {
+		Box::new(self.runtime.get_utxo(self.at(at), outpoint).compat())
+	}
+
+	fn list_unspent(
+		&self,
+		owner: H256,
+		after: Option<OutPoint>,
+		count: Option<u32>,
+		at: Option<H256>,
+	) -> FutureResult<Vec<UnspentOutput>> {
+		let count = count.unwrap_or(DEFAULT_PAGE_SIZE);
+		if count > MAX_PAGE_SIZE {
+			return Box::new(jsonrpc_core::futures::future::err(RpcError {
+				code: ErrorCode::ServerError(INVALID_PARAMS),
+				message: format!("count must not exceed {}", MAX_PAGE_SIZE),
+				data: None,
+			}));
+		}
+
+		Box::new(self.runtime.list_unspent(self.at(at), owner, after, count)
+			.map_ok(|unspent| unspent
+				.into_iter()
+				.map(|(outpoint, output)| UnspentOutput { outpoint, output })
+				.collect())
+			.boxed()
+			.compat())
+	}
+
+	fn decode_transaction(&self, transaction: Bytes) -> Result<Transaction> {
+		decode(&transaction)
+	}
+
+	fn validate_transaction(&self, transaction: Bytes, at: Option<H256>) -> FutureResult<Validity> {
+		let transaction = match decode(&transaction) {
+			Ok(transaction) => transaction,
+			Err(e) => return Box::new(jsonrpc_core::futures::future::err(e)),
+		};
+		let txid = UtxoModule::get_txid(&transaction);
+
+		// The fee is looked up at the same block the transaction is validated at
+		let at = self.at(at);
+		let validity = self.runtime.validate(at, transaction.clone());
+		let fee = self.runtime.fee(at, transaction);
+		Box::new(futures::future::try_join(validity, fee)
+			.map(move |result| match result? {
+				(Ok(valid), fee) => Ok(Validity {
+					txid,
+					fee: fee.unwrap_or_default(),
+					requires: valid.requires.into_iter().map(Bytes).collect(),
+					provides: valid.provides.into_iter().map(Bytes).collect(),
+				}),
+				(Err(e), _) => Err(invalid_transaction(e)),
+			})
+			.boxed()
+			.compat())
+	}
+
+	fn signing_payload(&self, transaction: Bytes) -> FutureResult<Bytes> {
+		let transaction = match decode(&transaction) {
+			Ok(transaction) => transaction,
+			Err(e) => return Box::new(jsonrpc_core::futures::future::err(e)),
+		};
+
+		Box::new(self.runtime.simple_transaction_bytes(self.at(None), transaction)
+			.map_ok(Bytes)
+			.boxed()
+			.compat())
+	}
+
+	fn estimate_fee(&self, transaction: Bytes, at: Option<H256>) -> FutureResult<FeeEstimate> {
+		let transaction = match decode(&transaction) {
+			Ok(transaction) => transaction,
+			Err(e) => return Box::new(jsonrpc_core::futures::future::err(e)),
+		};
+
+		let at = self.at(at);
+		let fee = self.runtime.fee(at, transaction.clone());
+		let weight = self.runtime.spend_weight(at, transaction);
+		Box::new(futures::future::try_join(fee, weight)
+			.map(|result| {
+				let (fee, weight) = result?;
+				let fee = fee.ok_or_else(|| RpcError {
+					code: ErrorCode::ServerError(INVALID_TRANSACTION),
+					message: "An input is missing or the outputs exceed the inputs.".into(),
+					data: None,
+				})?;
+				Ok(FeeEstimate { weight, fee })
+			})
+			.boxed()
+			.compat())
+	}
+
+	fn submit_transaction(&self, transaction: Bytes) -> FutureResult<H256> {
+		let transaction = match decode(&transaction) {
+			Ok(transaction) => transaction,
+			Err(e) => return Box::new(jsonrpc_core::futures::future::err(e)),
+		};
+		let txid = UtxoModule::get_txid(&transaction);
+
+		// UTXO transactions carry their own proofs, so they are submitted unsigned
+		let extrinsic = UncheckedExtrinsic::new_unsigned(Call::Utxo(utxo::Call::spend(transaction)));
+		let extrinsic = OpaqueExtrinsic::from_bytes(&extrinsic.encode())
+			.expect("an encoded extrinsic is an opaque extrinsic; qed");
+
+		let at = BlockId::hash(self.runtime.best_hash());
+		Box::new(self.pool.submit_one(&at, TransactionSource::External, extrinsic)
+			.map(move |result| match result {
+				Ok(_) => Ok(txid),
+				Err(e) => Err(RpcError {
+					code: ErrorCode::ServerError(POOL_ERROR),
+					message: "Transaction was not accepted into the pool.".into(),
+					data: e.into_pool_error().ok().map(|e| e.to_string().into()),
+				}),
+			})
+			.boxed()
+			.compat())
+	}
+}
+
+/// Decode a SCALE encoded transaction
+fn decode(transaction: &[u8]) -> Result<Transaction> {
+	Transaction::decode(&mut &transaction[..]).map_err(|e| RpcError {
+		code: ErrorCode::ServerError(INVALID_PARAMS),
+		message: "Unable to decode transaction.".into(),
+		data: Some(format!("{:?}", e).into()),
+	})
+}
+
+fn runtime_error(method: &str, error: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: format!("Runtime call {} failed.", method),
+		data: Some(format!("{:?}", error).into()),
+	}
+}
+
+fn invalid_transaction(error: DispatchError) -> RpcError {
+	let code = match error {
+		DispatchError::Module { error, .. } => Some(error.into()),
+		_ => None,
+	};
+	RpcError {
+		code: ErrorCode::ServerError(INVALID_TRANSACTION),
+		message: "Transaction is invalid.".into(),
+		data: code,
+	}
+}
+
+/// Dependencies of the RPC extensions of a full node
+pub struct FullDeps<C, P, A, T> {
+	/// The client instance to use
+	pub client: Arc<C>,
+	/// Transaction pool instance
+	pub pool: Arc<P>,
+	/// Mining worker, if the node mines
+	pub mining: Option<Arc<MiningWorker<A, T>>>,
+	/// Whether to deny unsafe calls
+	pub deny_unsafe: DenyUnsafe,
+}
+
+/// The RPC extensions of a full node
+pub fn create_full<C, P, A, T>(deps: FullDeps<C, P, A, T>) -> jsonrpc_core::IoHandler<sc_rpc::Metadata>
+where
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync + 'static,
+	C::Api: UtxoApi<Block> + DifficultyApi<Block, U256> + DifficultyHistoryApi<Block>,
+	P: TransactionPool<Block = Block> + 'static,
+	A: PowAlgorithm<Block, Difficulty = U256> + NonceSearch<Block> + Send + Sync + 'static,
+	T: Send + 'static,
+{
+	let FullDeps { client, pool, mining, deny_unsafe } = deps;
+
+	let mut io = jsonrpc_core::IoHandler::default();
+	io.extend_with(UtxoRpc::to_delegate(Utxo::new(FullAccess { client: client.clone() }, pool)));
+	io.extend_with(MiningRpc::to_delegate(Mining::new(mining, deny_unsafe)));
+	io.extend_with(PowRpc::to_delegate(Pow::new(FullAccess { client })));
+	io
+}
+
+/// Dependencies of the RPC extensions of a light node
+pub struct LightDeps<C, F, P> {
+	/// The client instance to use
+	pub client: Arc<C>,
+	/// Remote access to the blockchain
+	pub remote_blockchain: Arc<dyn RemoteBlockchain<Block>>,
+	/// Fetcher of data from full nodes
+	pub fetcher: Arc<F>,
+	/// Transaction pool instance
+	pub pool: Arc<P>,
+}
+
+/// The RPC extensions of a light node
+pub fn create_light<C, F, P>(deps: LightDeps<C, F, P>) -> jsonrpc_core::IoHandler<sc_rpc::Metadata>
+where
+	C: HeaderBackend<Block> + Send + Sync + 'static,
+	F: Fetcher<Block> + 'static,
+	P: TransactionPool<Block = Block> + 'static,
+{
+	let LightDeps { client, remote_blockchain, fetcher, pool } = deps;
+
+	let light_access = || LightAccess {
+		client: client.clone(),
+		remote_blockchain: remote_blockchain.clone(),
+		fetcher: fetcher.clone(),
+	};
+
+	let mut io = jsonrpc_core::IoHandler::default();
+	io.extend_with(UtxoRpc::to_delegate(Utxo::new(light_access(), pool)));
+	io.extend_with(PowRpc::to_delegate(Pow::new(light_access())));
+	io
+}
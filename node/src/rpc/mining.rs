@@ -0,0 +1,176 @@
+//! Mining RPCs of the node, in the `mining_*` namespace. They let miners outside the node
+//! work on the block its mining worker builds, and submit the nonces they find.
+//!
+//! Checking a submitted seal can take as much memory and time as mining a hash, so these
+//! RPCs are unsafe and only served where the node serves unsafe RPCs.
+
+use std::sync::Arc;
+
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use parity_scale_codec::Encode;
+use sc_consensus_pow::PowAlgorithm;
+use sc_rpc::DenyUnsafe;
+use serde::{Deserialize, Serialize};
+use sha3pow::NonceSearch;
+use sp_core::{H256, U256};
+use utxo_runtime::opaque::Block;
+
+use crate::mining::{MiningWorker, SubmitError};
+
+/// Error codes of the mining RPCs, following those of the UTXO RPCs
+const NOT_MINING: i64 = 5;
+const STALE_WORK: i64 = 6;
+const INVALID_SEAL: i64 = 7;
+const IMPORT_ERROR: i64 = 8;
+
+/// Block for a miner to work on
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Work {
+	/// Hash of the block being built on
+	pub parent_hash: H256,
+	/// Hash of the block's header without a seal, which the seal commits to
+	pub pre_hash: H256,
+	/// Difficulty the seal has to meet
+	pub difficulty: U256,
+}
+
+#[rpc]
+pub trait MiningRpc {
+	/// The block the node is mining. A miner searches for a nonce for which the hash of the
+	/// encoded `sha3pow::Compute` of the difficulty, pre-hash and nonce meets the difficulty,
+	/// using the PoW hash of the chain.
+	#[rpc(name = "mining_getWork")]
+	fn get_work(&self) -> Result<Work>;
+
+	/// Seal the block with pre-hash `pre_hash` with `nonce` and import it.
+	/// Returns the hash of the sealed block.
+	#[rpc(name = "mining_submitWork")]
+	fn submit_work(&self, pre_hash: H256, nonce: H256) -> Result<H256>;
+}
+
+/// Implements the mining RPCs on the node's mining worker, if it mines
+pub struct Mining<A, T> {
+	worker: Option<Arc<MiningWorker<A, T>>>,
+	deny_unsafe: DenyUnsafe,
+}
+
+impl<A, T> Mining<A, T> {
+	pub fn new(worker: Option<Arc<MiningWorker<A, T>>>, deny_unsafe: DenyUnsafe) -> Self {
+		Self { worker, deny_unsafe }
+	}
+
+	fn worker(&self) -> Result<&MiningWorker<A, T>> {
+		self.deny_unsafe.check_if_safe()?;
+		self.worker.as_deref().ok_or_else(|| not_mining("Node is not mining."))
+	}
+}
+
+impl<A, T> MiningRpc for Mining<A, T>
+where
+	A: PowAlgorithm<Block, Difficulty = U256> + NonceSearch<Block> + Send + Sync + 'static,
+	T: Send + 'static,
+{
+	fn get_work(&self) -> Result<Work> {
+		let metadata = self.worker()?.metadata().ok_or_else(|| not_mining("No block to mine yet."))?;
+		Ok(Work {
+			parent_hash: metadata.best_hash,
+			pre_hash: metadata.pre_hash,
+			difficulty: metadata.difficulty,
+		})
+	}
+
+	fn submit_work(&self, pre_hash: H256, nonce: H256) -> Result<H256> {
+		let worker = self.worker()?;
+		let metadata = match worker.metadata() {
+			Some(metadata) if metadata.pre_hash == pre_hash => metadata,
+			_ => return Err(submit_error(SubmitError::Stale)),
+		};
+		let seal = worker.algorithm()
+			.seal(&metadata.best_hash, &pre_hash, metadata.difficulty, nonce)
+			.map_err(|e| submit_error(SubmitError::Consensus(Box::new(e))))?;
+		worker.submit(pre_hash, seal.encode()).map_err(submit_error)
+	}
+}
+
+fn not_mining(message: &str) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(NOT_MINING),
+		message: message.into(),
+		data: None,
+	}
+}
+
+fn submit_error(error: SubmitError) -> RpcError {
+	let code = match error {
+		SubmitError::Stale => STALE_WORK,
+		SubmitError::InvalidSeal => INVALID_SEAL,
+		SubmitError::Consensus(_) => IMPORT_ERROR,
+	};
+	RpcError {
+		code: ErrorCode::ServerError(code),
+		message: error.to_string(),
+		data: None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mining::tests::{find_nonce, meets_difficulty, rebuild, test_worker, TestAlgorithm};
+
+	fn error_code<R: std::fmt::Debug>(result: Result<R>) -> ErrorCode {
+		result.unwrap_err().code
+	}
+
+	#[test]
+	fn submit_work_rejects_stale_work() {
+		let (worker, _) = test_worker();
+		let mining = Mining::new(Some(worker.clone()), DenyUnsafe::No);
+		let stale = mining.get_work().unwrap().pre_hash;
+		let current = rebuild(&worker, H256::repeat_byte(2), 0);
+		assert_eq!(mining.get_work().unwrap().pre_hash, current);
+
+		let nonce = find_nonce(&[], stale, meets_difficulty);
+		assert_eq!(error_code(mining.submit_work(stale, nonce)), ErrorCode::ServerError(STALE_WORK));
+	}
+
+	#[test]
+	fn submit_work_rejects_invalid_seal() {
+		let (worker, imported) = test_worker();
+		let mining = Mining::new(Some(worker), DenyUnsafe::No);
+		let pre_hash = mining.get_work().unwrap().pre_hash;
+
+		let too_easy = find_nonce(&[], pre_hash, |work| !meets_difficulty(work));
+		assert_eq!(error_code(mining.submit_work(pre_hash, too_easy)), ErrorCode::ServerError(INVALID_SEAL));
+		assert_eq!(imported.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+		let nonce = find_nonce(&[], pre_hash, meets_difficulty);
+		assert!(mining.submit_work(pre_hash, nonce).is_ok());
+		assert_eq!(imported.load(std::sync::atomic::Ordering::Relaxed), 1);
+		assert_eq!(error_code(mining.get_work()), ErrorCode::ServerError(NOT_MINING));
+	}
+
+	#[test]
+	fn unsafe_when_denied() {
+		let (worker, imported) = test_worker();
+		let pre_hash = Mining::new(Some(worker.clone()), DenyUnsafe::No).get_work().unwrap().pre_hash;
+		let mining = Mining::new(Some(worker), DenyUnsafe::Yes);
+		assert_eq!(error_code(mining.get_work()), ErrorCode::MethodNotFound);
+
+		let nonce = find_nonce(&[], pre_hash, meets_difficulty);
+		assert_eq!(error_code(mining.submit_work(pre_hash, nonce)), ErrorCode::MethodNotFound);
+		assert_eq!(imported.load(std::sync::atomic::Ordering::Relaxed), 0);
+	}
+
+	#[test]
+	fn not_mining_without_worker() {
+		let mining = Mining::<TestAlgorithm, ()>::new(None, DenyUnsafe::No);
+		assert_eq!(error_code(mining.get_work()), ErrorCode::ServerError(NOT_MINING));
+		assert_eq!(
+			error_code(mining.submit_work(H256::zero(), H256::zero())),
+			ErrorCode::ServerError(NOT_MINING),
+		);
+	}
+}
@@ -0,0 +1,81 @@
+//! Proof of work RPCs of the node, in the `pow_*` namespace. They report the difficulty and
+//! hashrate of the network for monitoring, from the `DifficultyApi` and `DifficultyHistoryApi`
+//! runtime APIs.
+
+use std::sync::Arc;
+
+use futures::{FutureExt, TryFutureExt};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+use sp_core::{H256, U256};
+
+use super::{FutureResult, RuntimeAccess};
+
+/// Difficulty and timestamp of a past block
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PastBlock {
+	/// Difficulty the block was mined at
+	pub difficulty: U256,
+	/// Timestamp of the block, in milliseconds
+	pub timestamp: u64,
+}
+
+/// Difficulty and hashrate of the network
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkStats {
+	/// Difficulty of the next block
+	pub difficulty: U256,
+	/// The past blocks the difficulty adjustment is computed from, from earliest to latest
+	pub past_blocks: Vec<PastBlock>,
+	/// Average milliseconds between the past blocks, if there are two or more
+	pub average_block_time: Option<u64>,
+	/// Hashes per second the network is estimated to make, from the past blocks
+	pub hashrate: Option<U256>,
+}
+
+#[rpc]
+pub trait PowRpc<BlockHash> {
+	/// Difficulty, past blocks and estimated hashrate of the network, at block `at` or the
+	/// best block
+	#[rpc(name = "pow_networkStats")]
+	fn network_stats(&self, at: Option<BlockHash>) -> FutureResult<NetworkStats>;
+}
+
+/// Implements `PowRpc` on top of a `RuntimeAccess`
+pub struct Pow<R> {
+	runtime: Arc<R>,
+}
+
+impl<R: RuntimeAccess> Pow<R> {
+	pub fn new(runtime: R) -> Self {
+		Pow { runtime: Arc::new(runtime) }
+	}
+}
+
+impl<R: RuntimeAccess> PowRpc<H256> for Pow<R> {
+	fn network_stats(&self, at: Option<H256>) -> FutureResult<NetworkStats> {
+		// Every call has to see the same block
+		let at = at.unwrap_or_else(|| self.runtime.best_hash());
+
+		let difficulty = self.runtime.difficulty(at);
+		let past = self.runtime.past_difficulties_and_timestamps(at);
+		let average_block_time = self.runtime.average_block_time(at);
+		let hashrate = self.runtime.network_hashrate(at);
+
+		Box::new(futures::future::try_join4(difficulty, past, average_block_time, hashrate)
+			.map_ok(|(difficulty, past, average_block_time, hashrate)| NetworkStats {
+				difficulty,
+				past_blocks: past
+					.into_iter()
+					.flatten()
+					.map(|past| PastBlock { difficulty: past.difficulty, timestamp: past.timestamp })
+					.collect(),
+				average_block_time,
+				hashrate,
+			})
+			.boxed()
+			.compat())
+	}
+}
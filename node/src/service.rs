@@ -7,20 +7,24 @@ use sc_service::{error::Error as ServiceError, Configuration, PartialComponents,
 use sp_inherents::InherentDataProviders;
 use sc_executor::native_executor_instance;
 pub use sc_executor::NativeExecutor;
-use sha3pow::Sha3Algorithm;
+use sha3pow::ChainAlgorithm;
 use sc_network::{config::DummyFinalityProofRequestBuilder};
 use core::clone::Clone;
 use sp_core::sr25519;
 use parity_scale_codec::Encode;
 use sp_consensus::import_queue::BasicQueue;
+use sp_transaction_pool::TransactionPool;
 use sp_api::TransactionFor;
 use sc_client_api::backend::RemoteBackend;
+use crate::chain_spec::Extensions;
+use crate::mining::MiningConfig;
 
 // Our native executor instance.
 native_executor_instance!(
 	pub Executor,
 	utxo_runtime::api::dispatch,
 	utxo_runtime::native_version,
+	frame_benchmarking::benchmarking::HostFunctions,
 );
 
 pub fn build_inherent_data_providers(sr25519_public_key: sr25519::Public) -> Result<InherentDataProviders, ServiceError> {
@@ -41,6 +45,17 @@ pub fn build_inherent_data_providers(sr25519_public_key: sr25519::Public) -> Res
 	Ok(providers)
 }
 
+/// The PoW settings of the chain spec, checked to keep verification cheap
+fn pow_settings(config: &Configuration) -> Result<Extensions, ServiceError> {
+	let extensions = sc_chain_spec::get_extension::<Extensions>(config.chain_spec.extensions())
+		.cloned()
+		.unwrap_or_default();
+	if let Some(params) = &extensions.argon2 {
+		params.validate().map_err(ServiceError::Other)?;
+	}
+	Ok(extensions)
+}
+
 type FullClient = sc_service::TFullClient<Block, RuntimeApi, Executor>;
 type FullBackend = sc_service::TFullBackend<Block>;
 type FullSelectChain = sc_consensus::LongestChain<FullBackend, Block>;
@@ -52,10 +67,11 @@ pub fn new_partial(config: &Configuration, sr25519_public_key: sr25519::Public)
 		FullClient, FullBackend, FullSelectChain,
 		BasicQueue<Block, TransactionFor<FullClient, Block>>,
 		sc_transaction_pool::FullPool<Block, FullClient>,
-		sc_consensus_pow::PowBlockImport<Block, Arc<FullClient>, FullClient, FullSelectChain, Sha3Algorithm<FullClient>, impl sp_consensus::CanAuthorWith<Block>>,
+		sc_consensus_pow::PowBlockImport<Block, Arc<FullClient>, FullClient, FullSelectChain, ChainAlgorithm<FullClient>, impl sp_consensus::CanAuthorWith<Block>>,
 	>,
 ServiceError> {
 	let inherent_data_providers = build_inherent_data_providers(sr25519_public_key)?;
+	let pow_settings = pow_settings(config)?;
 
 	let (client, backend, keystore, task_manager) =
 		sc_service::new_full_parts::<Block, RuntimeApi, Executor>(&config)?;
@@ -76,7 +92,7 @@ ServiceError> {
 	let pow_block_import = sc_consensus_pow::PowBlockImport::new(
 		client.clone(),
 		client.clone(),
-		Sha3Algorithm::new(client.clone()),
+		pow_settings.pow_algorithm(client.clone()),
 		0, // check inherents starting at block 0
 		Some(select_chain.clone()),
 		inherent_data_providers.clone(),
@@ -87,7 +103,7 @@ ServiceError> {
 		Box::new(pow_block_import.clone()),
 		None,
 		None,
-		Sha3Algorithm::new(client.clone()),
+		pow_settings.pow_algorithm(client.clone()),
 		inherent_data_providers.clone(),
 		&task_manager.spawn_handle(),
 		config.prometheus_registry(),
@@ -100,13 +116,18 @@ ServiceError> {
 	})
 }
 
-/// Builds a new service for a full client.
-pub fn new_full(config: Configuration, sr25519_public_key: sr25519::Public) -> Result<TaskManager, ServiceError> {
+/// Builds a new service for a full client. An authority mines as `mining` says.
+pub fn new_full(
+	config: Configuration,
+	sr25519_public_key: sr25519::Public,
+	mining: MiningConfig,
+) -> Result<TaskManager, ServiceError> {
 	let sc_service::PartialComponents {
 		client, backend, mut task_manager, import_queue, keystore, select_chain, transaction_pool,
 		inherent_data_providers,
 		other: pow_block_import,
 	} = new_partial(&config, sr25519_public_key)?;
+	let pow_settings = pow_settings(&config)?;
 
 	let (network, network_status_sinks, system_rpc_tx, network_starter) =
 		sc_service::build_network(sc_service::BuildNetworkParams {
@@ -122,17 +143,62 @@ pub fn new_full(config: Configuration, sr25519_public_key: sr25519::Public) -> R
 		})?;
 
 	let role = config.role.clone();
+	if mining.stratum.is_some() && !role.is_authority() {
+		return Err(ServiceError::Other("Serving Stratum requires --validator".into()));
+	}
 	let prometheus_registry = config.prometheus_registry().cloned();
 	let telemetry_connection_sinks = sc_service::TelemetryConnectionSinks::default();
 
+	let mining_worker = if role.is_authority() {
+		let proposer = sc_basic_authorship::ProposerFactory::new(
+			client.clone(),
+			transaction_pool.clone(),
+			prometheus_registry.as_ref(),
+		);
+
+		let can_author_with =
+			sp_consensus::CanAuthorWithNativeVersion::new(client.executor().clone());
+
+		Some(crate::mining::start_mining_worker(
+			Box::new(pow_block_import),
+			pow_settings.pow_algorithm(client.clone()),
+			proposer,
+			network.clone(),
+			std::time::Duration::new(2, 0),
+			select_chain,
+			transaction_pool.import_notification_stream(),
+			inherent_data_providers,
+			can_author_with,
+			prometheus_registry.as_ref(),
+		))
+	} else {
+		None
+	};
+
+	let rpc_extensions_builder = {
+		let client = client.clone();
+		let pool = transaction_pool.clone();
+		let mining = mining_worker.clone();
+
+		Box::new(move |deny_unsafe, _| {
+			let deps = crate::rpc::FullDeps {
+				client: client.clone(),
+				pool: pool.clone(),
+				mining: mining.clone(),
+				deny_unsafe,
+			};
+			crate::rpc::create_full(deps)
+		})
+	};
+
 	sc_service::spawn_tasks(sc_service::SpawnTasksParams {
-		network: network.clone(),
+		network,
 		client: client.clone(),
 		keystore: keystore.clone(),
 		task_manager: &mut task_manager,
 		transaction_pool: transaction_pool.clone(),
 		telemetry_connection_sinks: telemetry_connection_sinks.clone(),
-		rpc_extensions_builder: Box::new(|_, _| ()),
+		rpc_extensions_builder,
 		on_demand: None,
 		remote_blockchain: None,
 		backend, network_status_sinks, system_rpc_tx, config,
@@ -140,34 +206,18 @@ pub fn new_full(config: Configuration, sr25519_public_key: sr25519::Public) -> R
 
 
 
-	if role.is_authority() {
-		let proposer = sc_basic_authorship::ProposerFactory::new(
-			client.clone(),
-			transaction_pool,
-			prometheus_registry.as_ref(),
-		);
-
-		// The number of rounds of mining to try in a single call
-		let rounds = 500;
+	task_manager.spawn_handle().spawn(
+		"utxo-lock-promotion",
+		crate::locks::promote_unlocked(client, transaction_pool.pool().clone()),
+	);
 
-		let can_author_with =
-			sp_consensus::CanAuthorWithNativeVersion::new(client.executor().clone());
+	if let Some(worker) = mining_worker {
+		crate::mining::start_cpu_miner(worker.clone(), mining.threads, mining.rounds);
 
-		sc_consensus_pow::start_mine(
-			Box::new(pow_block_import),
-			client.clone(),
-			Sha3Algorithm::new(client.clone()),
-			proposer,
-			None, // No preruntime digests
-			rounds,
-			network,
-			std::time::Duration::new(2, 0),
-			// Choosing not to supply a select_chain means we will use the client's
-			// possibly-outdated metadata when fetching the block to mine on
-			Some(select_chain),
-			inherent_data_providers,
-			can_author_with,
-		);
+		if let Some(stratum) = mining.stratum {
+			crate::stratum::start_stratum_server(worker, stratum)
+				.map_err(|e| ServiceError::Other(format!("Starting Stratum server failed: {}", e)))?;
+		}
 	}
 
 	network_starter.start_network();
@@ -178,6 +228,7 @@ pub fn new_full(config: Configuration, sr25519_public_key: sr25519::Public) -> R
 pub fn new_light(config: Configuration, sr25519_public_key: sr25519::Public) -> Result<TaskManager, ServiceError> {
 	let (client, backend, keystore, mut task_manager, on_demand) =
 		sc_service::new_light_parts::<Block, RuntimeApi, Executor>(&config)?;
+	let pow_settings = pow_settings(&config)?;
 
 	let transaction_pool = Arc::new(sc_transaction_pool::BasicPool::new_light(
 		config.transaction_pool.clone(),
@@ -195,7 +246,7 @@ pub fn new_light(config: Configuration, sr25519_public_key: sr25519::Public) ->
 	let pow_block_import = sc_consensus_pow::PowBlockImport::new(
 		client.clone(),
 		client.clone(),
-		Sha3Algorithm::new(client.clone()),
+		pow_settings.pow_algorithm(client.clone()),
 		0, // check inherents starting at block 0
 		Some(select_chain),
 		inherent_data_providers.clone(),
@@ -207,7 +258,7 @@ pub fn new_light(config: Configuration, sr25519_public_key: sr25519::Public) ->
 		Box::new(pow_block_import),
 		None,
 		None,
-		Sha3Algorithm::new(client.clone()),
+		pow_settings.pow_algorithm(client.clone()),
 		inherent_data_providers.clone(),
 		&task_manager.spawn_handle(),
 		config.prometheus_registry(),
@@ -228,12 +279,19 @@ pub fn new_light(config: Configuration, sr25519_public_key: sr25519::Public) ->
 			finality_proof_provider: None,
 		})?;
 
+	let rpc_extensions = crate::rpc::create_light(crate::rpc::LightDeps {
+		client: client.clone(),
+		remote_blockchain: backend.remote_blockchain(),
+		fetcher: on_demand.clone(),
+		pool: transaction_pool.clone(),
+	});
+
 	sc_service::spawn_tasks(sc_service::SpawnTasksParams {
 		remote_blockchain: Some(backend.remote_blockchain()),
 		transaction_pool,
 		task_manager: &mut task_manager,
 		on_demand: Some(on_demand),
-		rpc_extensions_builder: Box::new(|_, _| ()),
+		rpc_extensions_builder: Box::new(sc_service::NoopRpcExtensionBuilder(rpc_extensions)),
 		telemetry_connection_sinks: sc_service::TelemetryConnectionSinks::default(),
 		config,
 		client,
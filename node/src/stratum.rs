@@ -0,0 +1,559 @@
+//! Stratum v1 style mining server, so a pool can hand the block the node mines out to many miners.
+//!
+//! Messages are JSON objects, one per line. Miners call
+//!
+//! - `mining.subscribe()`, answered with `[subscriptions, extranonce1, extranonce2_size]`
+//! - `mining.authorize(worker, password)`, which accepts any worker
+//! - `mining.submit(worker, job_id, extranonce2)`
+//!
+//! and the server notifies them with `mining.set_difficulty(share_difficulty)` and
+//! `mining.notify(job_id, pre_hash, parent_hash, difficulty, clean_jobs)`. Hashes and difficulty
+//! are big endian hex. A miner's nonce is its `extranonce1` followed by the `extranonce2` it
+//! searches over, and the nonce is a share when the chain's PoW hash of the encoded
+//! `sha3pow::Compute` of the job's difficulty, pre-hash and nonce meets the share difficulty.
+//! Shares that also meet the job's difficulty are imported as blocks.
+
+use std::{
+	collections::{HashSet, VecDeque},
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+	sync::{
+		atomic::{AtomicU32, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::Duration,
+};
+
+use log::{debug, info, warn};
+use parity_scale_codec::Encode;
+use sc_consensus_pow::PowAlgorithm;
+use serde_json::{json, Value};
+use sha3pow::NonceSearch;
+use sp_core::{H256, U256};
+use utxo_runtime::opaque::Block;
+
+use crate::mining::{MiningMetadata, MiningWorker, SubmitError};
+
+/// How often the server checks the mining worker for a new block
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Number of jobs shares are still accepted for after a newer job on the same parent
+const MAX_JOBS: usize = 8;
+
+/// Number of miners connected at once
+const MAX_CONNECTIONS: usize = 256;
+
+/// Longest request a miner may send, in bytes
+const MAX_LINE: u64 = 4096;
+
+/// How long a miner may stay silent before it is disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long sending to a miner may block before it is disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes of the nonce the server picks for each connection
+const EXTRANONCE1_SIZE: usize = 4;
+
+/// Bytes of the nonce a miner searches over
+const EXTRANONCE2_SIZE: usize = 32 - EXTRANONCE1_SIZE;
+
+/// Stratum error codes
+const OTHER_ERROR: i64 = 20;
+const JOB_NOT_FOUND: i64 = 21;
+const DUPLICATE_SHARE: i64 = 22;
+const LOW_DIFFICULTY_SHARE: i64 = 23;
+const UNAUTHORIZED_WORKER: i64 = 24;
+const NOT_SUBSCRIBED: i64 = 25;
+
+/// Where the Stratum server listens, and how hard its shares are
+#[derive(Clone, Debug)]
+pub struct StratumConfig {
+	/// Address to accept miners on
+	pub listen: SocketAddr,
+	/// Difficulty a share has to meet, capped at the difficulty of the block
+	pub share_difficulty: u64,
+}
+
+/// A block handed out to miners
+struct Job {
+	id: String,
+	metadata: MiningMetadata,
+	share_difficulty: u64,
+	/// Nonces already submitted as shares
+	nonces: HashSet<H256>,
+}
+
+impl Job {
+	fn notify(&self, clean: bool) -> [Value; 2] {
+		[
+			notification("mining.set_difficulty", json!([self.share_difficulty])),
+			notification("mining.notify", json!([
+				self.id,
+				hex::encode(self.metadata.pre_hash),
+				hex::encode(self.metadata.best_hash),
+				hex::encode(u256_to_big_endian(self.metadata.difficulty)),
+				clean,
+			])),
+		]
+	}
+}
+
+/// A connected miner
+struct Connection {
+	extranonce1: [u8; EXTRANONCE1_SIZE],
+	writer: Mutex<TcpStream>,
+	state: Mutex<ConnectionState>,
+}
+
+#[derive(Default)]
+struct ConnectionState {
+	subscribed: bool,
+	worker: Option<String>,
+	accepted: u64,
+	rejected: u64,
+}
+
+impl Connection {
+	fn send(&self, message: &Value) -> io::Result<()> {
+		let mut line = message.to_string();
+		line.push('\n');
+		self.writer.lock().expect("stratum connection lock poisoned").write_all(line.as_bytes())
+	}
+
+	/// Disconnect the miner, which ends serving it
+	fn close(&self) {
+		let _ = self.writer.lock().expect("stratum connection lock poisoned").shutdown(Shutdown::Both);
+	}
+}
+
+struct Server<A, T> {
+	worker: Arc<MiningWorker<A, T>>,
+	share_difficulty: u64,
+	/// Recent jobs, newest last
+	jobs: Mutex<VecDeque<Job>>,
+	connections: Mutex<Vec<Arc<Connection>>>,
+	next_connection: AtomicU32,
+	next_job: AtomicU32,
+}
+
+/// Start accepting Stratum miners on `config.listen`, handing out the work of `worker`
+pub fn start_stratum_server<A, T>(worker: Arc<MiningWorker<A, T>>, config: StratumConfig) -> io::Result<()> where
+	A: PowAlgorithm<Block, Difficulty = U256> + NonceSearch<Block> + Send + Sync + 'static,
+	T: Send + 'static,
+{
+	let listener = TcpListener::bind(config.listen)?;
+	info!("⛏  Stratum server listening on {}", config.listen);
+
+	let server = Arc::new(Server {
+		worker,
+		share_difficulty: config.share_difficulty.max(1),
+		jobs: Mutex::new(VecDeque::new()),
+		connections: Mutex::new(Vec::new()),
+		next_connection: AtomicU32::new(0),
+		next_job: AtomicU32::new(0),
+	});
+
+	let notifying = server.clone();
+	thread::spawn(move || loop {
+		notifying.update_job();
+		thread::sleep(POLL_INTERVAL);
+	});
+
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			match stream {
+				Ok(stream) => match server.connect(&stream) {
+					Ok(connection) => {
+						let server = server.clone();
+						thread::spawn(move || server.serve(stream, connection));
+					},
+					Err(e) => warn!("Refusing stratum connection: {}", e),
+				},
+				Err(e) => warn!("Accepting stratum connection failed: {}", e),
+			}
+		}
+	});
+
+	Ok(())
+}
+
+impl<A, T> Server<A, T> where
+	A: PowAlgorithm<Block, Difficulty = U256> + NonceSearch<Block> + Send + Sync + 'static,
+	T: Send + 'static,
+{
+	/// Turn new work of the mining worker into a job and notify every subscribed miner
+	fn update_job(&self) {
+		let metadata = match self.worker.metadata() {
+			Some(metadata) => metadata,
+			None => return,
+		};
+
+		let messages = {
+			let mut jobs = self.jobs.lock().expect("stratum jobs lock poisoned");
+			let clean = match jobs.back() {
+				Some(job) if job.metadata == metadata => return,
+				Some(job) => job.metadata.best_hash != metadata.best_hash,
+				None => true,
+			};
+			if clean {
+				jobs.clear();
+			} else if jobs.len() == MAX_JOBS {
+				jobs.pop_front();
+			}
+
+			let job = Job {
+				id: format!("{:x}", self.next_job.fetch_add(1, Ordering::Relaxed)),
+				share_difficulty: U256::from(self.share_difficulty).min(metadata.difficulty).low_u64(),
+				metadata,
+				nonces: HashSet::new(),
+			};
+			debug!(target: "stratum", "New job {} on top of {}", job.id, job.metadata.best_hash);
+			let messages = job.notify(clean);
+			jobs.push_back(job);
+			messages
+		};
+
+		// Sent without holding the connections, as a slow miner blocks until the write timeout
+		let connections = self.connections.lock().expect("stratum connections lock poisoned").clone();
+		let failed = connections.into_iter()
+			.filter(|connection| {
+				let subscribed = connection.state.lock().expect("stratum connection lock poisoned").subscribed;
+				subscribed && messages.iter().any(|message| connection.send(message).is_err())
+			})
+			.collect::<Vec<_>>();
+		if failed.is_empty() {
+			return;
+		}
+		self.connections.lock().expect("stratum connections lock poisoned")
+			.retain(|connection| !failed.iter().any(|other| Arc::ptr_eq(other, connection)));
+		failed.iter().for_each(|connection| connection.close());
+	}
+
+	/// Register a miner connecting on `stream`, unless `MAX_CONNECTIONS` are connected already
+	fn connect(&self, stream: &TcpStream) -> io::Result<Arc<Connection>> {
+		let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+		let mut connections = self.connections.lock().expect("stratum connections lock poisoned");
+		if connections.len() >= MAX_CONNECTIONS {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("{} has to wait, {} miners are connected already", peer, connections.len()),
+			));
+		}
+		stream.set_read_timeout(Some(READ_TIMEOUT))?;
+		stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+		let connection = Arc::new(Connection {
+			extranonce1: self.next_connection.fetch_add(1, Ordering::Relaxed).to_be_bytes(),
+			writer: Mutex::new(stream.try_clone()?),
+			state: Mutex::new(ConnectionState::default()),
+		});
+		connections.push(connection.clone());
+		debug!(target: "stratum", "Miner connected from {}", peer);
+		Ok(connection)
+	}
+
+	/// Answer the requests of one miner until it disconnects
+	fn serve(&self, stream: TcpStream, connection: Arc<Connection>) {
+		let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+		let mut reader = BufReader::new(stream);
+		let mut line = String::new();
+		loop {
+			line.clear();
+			match (&mut reader).take(MAX_LINE).read_line(&mut line) {
+				Ok(0) | Err(_) => break,
+				Ok(read) if read as u64 == MAX_LINE && !line.ends_with('\n') => {
+					debug!(target: "stratum", "Miner {} sent a request over {} bytes", peer, MAX_LINE);
+					break
+				},
+				Ok(_) => (),
+			}
+			if line.trim().is_empty() {
+				continue
+			}
+			let (id, response) = match serde_json::from_str::<Value>(&line) {
+				Ok(request) => (request["id"].clone(), self.handle(&connection, &request)),
+				Err(_) => (Value::Null, Err((OTHER_ERROR, "Invalid JSON"))),
+			};
+			let sent = connection.send(&response_message(id, response.as_ref().map(|(result, _)| result)))
+				.and_then(|_| match response {
+					Ok((_, follow_up)) => follow_up.iter().try_for_each(|message| connection.send(message)),
+					Err(_) => Ok(()),
+				});
+			if sent.is_err() {
+				break
+			}
+		}
+
+		self.connections.lock().expect("stratum connections lock poisoned")
+			.retain(|other| !Arc::ptr_eq(other, &connection));
+		let state = connection.state.lock().expect("stratum connection lock poisoned");
+		info!(
+			"⛏  Stratum miner {} ({}) disconnected after {} accepted and {} rejected shares",
+			state.worker.as_deref().unwrap_or("unauthorized"),
+			peer,
+			state.accepted,
+			state.rejected,
+		);
+	}
+
+	/// Result of a request, and notifications to send after it
+	fn handle(&self, connection: &Connection, request: &Value) -> Result<(Value, Vec<Value>), (i64, &'static str)> {
+		let params = request["params"].as_array().map(Vec::as_slice).unwrap_or_default();
+		match request["method"].as_str() {
+			Some("mining.subscribe") => {
+				connection.state.lock().expect("stratum connection lock poisoned").subscribed = true;
+				let subscription = hex::encode(connection.extranonce1);
+				let result = json!([
+					[["mining.set_difficulty", subscription], ["mining.notify", subscription]],
+					subscription,
+					EXTRANONCE2_SIZE,
+				]);
+				let jobs = self.jobs.lock().expect("stratum jobs lock poisoned");
+				let follow_up = jobs.back().map(|job| job.notify(true).to_vec()).unwrap_or_default();
+				Ok((result, follow_up))
+			},
+			Some("mining.authorize") => {
+				let worker = params.get(0).and_then(Value::as_str).ok_or((OTHER_ERROR, "Missing worker name"))?;
+				connection.state.lock().expect("stratum connection lock poisoned").worker = Some(worker.into());
+				Ok((Value::Bool(true), Vec::new()))
+			},
+			Some("mining.submit") => {
+				let accepted = self.submit(connection, params);
+				let mut state = connection.state.lock().expect("stratum connection lock poisoned");
+				match accepted {
+					Ok(()) => state.accepted += 1,
+					Err(_) => state.rejected += 1,
+				}
+				accepted.map(|()| (Value::Bool(true), Vec::new()))
+			},
+			_ => Err((OTHER_ERROR, "Unknown method")),
+		}
+	}
+
+	/// Check a share, and import it as a block if it meets the block difficulty
+	fn submit(&self, connection: &Connection, params: &[Value]) -> Result<(), (i64, &'static str)> {
+		let worker = {
+			let state = connection.state.lock().expect("stratum connection lock poisoned");
+			if !state.subscribed {
+				return Err((NOT_SUBSCRIBED, "Not subscribed"));
+			}
+			match (&state.worker, params.get(0).and_then(Value::as_str)) {
+				(Some(authorized), Some(worker)) if authorized == worker => worker.to_string(),
+				_ => return Err((UNAUTHORIZED_WORKER, "Unauthorized worker")),
+			}
+		};
+		let job_id = params.get(1).and_then(Value::as_str).ok_or((OTHER_ERROR, "Missing job id"))?;
+		let extranonce2 = params.get(2).and_then(Value::as_str)
+			.and_then(|extranonce2| hex::decode(extranonce2).ok())
+			.filter(|extranonce2| extranonce2.len() == EXTRANONCE2_SIZE)
+			.ok_or((OTHER_ERROR, "Invalid extranonce2"))?;
+
+		let mut nonce = H256::zero();
+		nonce[..EXTRANONCE1_SIZE].copy_from_slice(&connection.extranonce1);
+		nonce[EXTRANONCE1_SIZE..].copy_from_slice(&extranonce2);
+
+		let (metadata, share_difficulty) = {
+			let jobs = self.jobs.lock().expect("stratum jobs lock poisoned");
+			let job = jobs.iter().find(|job| job.id == job_id).ok_or((JOB_NOT_FOUND, "Job not found"))?;
+			if job.nonces.contains(&nonce) {
+				return Err((DUPLICATE_SHARE, "Duplicate share"));
+			}
+			(job.metadata.clone(), job.share_difficulty)
+		};
+
+		// Computed without holding the jobs, as memory-hard seals take a while
+		let seal = self.worker.algorithm()
+			.seal(&metadata.best_hash, &metadata.pre_hash, metadata.difficulty, nonce)
+			.map_err(|e| {
+				warn!("Computing seal of stratum share failed: {}", e);
+				(OTHER_ERROR, "Computing seal failed")
+			})?;
+		if !sha3pow::hash_meets_difficulty(&seal.work, share_difficulty.into()) {
+			return Err((LOW_DIFFICULTY_SHARE, "Low difficulty share"));
+		}
+
+		let mut jobs = self.jobs.lock().expect("stratum jobs lock poisoned");
+		if let Some(job) = jobs.iter_mut().find(|job| job.id == job_id) {
+			if !job.nonces.insert(nonce) {
+				return Err((DUPLICATE_SHARE, "Duplicate share"));
+			}
+		}
+		drop(jobs);
+
+		if sha3pow::hash_meets_difficulty(&seal.work, metadata.difficulty) {
+			match self.worker.submit(metadata.pre_hash, seal.encode()) {
+				Ok(hash) => info!("⛏  Stratum miner {} found block {}", worker, hash),
+				// Another miner was faster, the share still counts
+				Err(SubmitError::Stale) => (),
+				Err(e) => warn!("Importing block found by stratum miner {} failed: {}", worker, e),
+			}
+		}
+		Ok(())
+	}
+}
+
+fn notification(method: &str, params: Value) -> Value {
+	json!({ "id": null, "method": method, "params": params })
+}
+
+fn response_message(id: Value, result: Result<&Value, &(i64, &str)>) -> Value {
+	match result {
+		Ok(result) => json!({ "id": id, "result": result, "error": null }),
+		Err((code, message)) => json!({ "id": id, "result": null, "error": [code, message, null] }),
+	}
+}
+
+fn u256_to_big_endian(value: U256) -> [u8; 32] {
+	let mut bytes = [0; 32];
+	value.to_big_endian(&mut bytes);
+	bytes
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mining::tests::{find_nonce, meets_difficulty, rebuild, test_worker, TestAlgorithm};
+	use std::sync::atomic::AtomicU64;
+
+	const SHARE_DIFFICULTY: u64 = 4;
+
+	fn server() -> (Server<TestAlgorithm, ()>, Arc<AtomicU64>) {
+		let (worker, imported) = test_worker();
+		let server = Server {
+			worker,
+			share_difficulty: SHARE_DIFFICULTY,
+			jobs: Mutex::new(VecDeque::new()),
+			connections: Mutex::new(Vec::new()),
+			next_connection: AtomicU32::new(0),
+			next_job: AtomicU32::new(0),
+		};
+		server.update_job();
+		(server, imported)
+	}
+
+	/// Server and miner ends of a loopback connection
+	fn socket_pair() -> (TcpStream, TcpStream) {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let miner = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		(listener.accept().unwrap().0, miner)
+	}
+
+	/// A subscribed and authorized miner, and its end of the connection
+	fn miner(server: &Server<TestAlgorithm, ()>) -> (Arc<Connection>, TcpStream) {
+		let (stream, miner) = socket_pair();
+		let connection = server.connect(&stream).unwrap();
+		for request in &[
+			json!({ "id": 1, "method": "mining.subscribe", "params": [] }),
+			json!({ "id": 2, "method": "mining.authorize", "params": ["worker", "x"] }),
+		] {
+			server.handle(&connection, request).unwrap();
+		}
+		(connection, miner)
+	}
+
+	fn latest_job(server: &Server<TestAlgorithm, ()>) -> (String, H256) {
+		let jobs = server.jobs.lock().unwrap();
+		let job = jobs.back().unwrap();
+		(job.id.clone(), job.metadata.pre_hash)
+	}
+
+	/// Nonce of `connection` whose work on `pre_hash` satisfies `accept`
+	fn nonce(connection: &Connection, pre_hash: H256, accept: impl Fn(&H256) -> bool) -> H256 {
+		find_nonce(&connection.extranonce1, pre_hash, accept)
+	}
+
+	fn submit(server: &Server<TestAlgorithm, ()>, connection: &Connection, job_id: &str, nonce: H256) -> Result<(), i64> {
+		let params = [json!("worker"), json!(job_id), json!(hex::encode(&nonce[EXTRANONCE1_SIZE..]))];
+		server.submit(connection, &params).map_err(|(code, _)| code)
+	}
+
+	fn meets_share_difficulty(work: &H256) -> bool {
+		sha3pow::hash_meets_difficulty(work, SHARE_DIFFICULTY.into())
+	}
+
+	#[test]
+	fn shares_are_accepted_at_the_share_difficulty() {
+		let (server, imported) = server();
+		let (connection, _miner) = miner(&server);
+		let (job_id, pre_hash) = latest_job(&server);
+
+		let share = nonce(&connection, pre_hash, |work| meets_share_difficulty(work) && !meets_difficulty(work));
+		assert_eq!(submit(&server, &connection, &job_id, share), Ok(()));
+		assert_eq!(imported.load(Ordering::Relaxed), 0);
+
+		let low = nonce(&connection, pre_hash, |work| !meets_share_difficulty(work));
+		assert_eq!(submit(&server, &connection, &job_id, low), Err(LOW_DIFFICULTY_SHARE));
+	}
+
+	#[test]
+	fn shares_meeting_the_block_difficulty_are_imported() {
+		let (server, imported) = server();
+		let (connection, _miner) = miner(&server);
+		let (job_id, pre_hash) = latest_job(&server);
+
+		let solution = nonce(&connection, pre_hash, meets_difficulty);
+		assert_eq!(submit(&server, &connection, &job_id, solution), Ok(()));
+		assert_eq!(imported.load(Ordering::Relaxed), 1);
+		assert!(server.worker.metadata().is_none());
+	}
+
+	#[test]
+	fn duplicate_shares_are_rejected() {
+		let (server, _) = server();
+		let (connection, _miner) = miner(&server);
+		let (job_id, pre_hash) = latest_job(&server);
+
+		let share = nonce(&connection, pre_hash, |work| meets_share_difficulty(work) && !meets_difficulty(work));
+		assert_eq!(submit(&server, &connection, &job_id, share), Ok(()));
+		assert_eq!(submit(&server, &connection, &job_id, share), Err(DUPLICATE_SHARE));
+	}
+
+	#[test]
+	fn jobs_go_stale_after_max_jobs() {
+		let (server, _) = server();
+		let (connection, _miner) = miner(&server);
+		let (first, first_pre_hash) = latest_job(&server);
+		let parent = server.worker.metadata().unwrap().best_hash;
+
+		// Newer jobs on the same parent keep the last `MAX_JOBS` jobs
+		let (second, second_pre_hash) = {
+			rebuild(&server.worker, parent, 1);
+			server.update_job();
+			latest_job(&server)
+		};
+		for salt in 2..MAX_JOBS as u8 {
+			rebuild(&server.worker, parent, salt);
+			server.update_job();
+		}
+		let share = nonce(&connection, first_pre_hash, |work| meets_share_difficulty(work) && !meets_difficulty(work));
+		assert_eq!(submit(&server, &connection, &first, share), Ok(()));
+
+		rebuild(&server.worker, parent, MAX_JOBS as u8);
+		server.update_job();
+		assert_eq!(server.jobs.lock().unwrap().len(), MAX_JOBS);
+		assert_eq!(submit(&server, &connection, &first, share), Err(JOB_NOT_FOUND));
+		let share = nonce(&connection, second_pre_hash, |work| meets_share_difficulty(work) && !meets_difficulty(work));
+		assert_eq!(submit(&server, &connection, &second, share), Ok(()));
+
+		// A new parent makes every job stale
+		rebuild(&server.worker, H256::repeat_byte(2), 0);
+		server.update_job();
+		assert_eq!(server.jobs.lock().unwrap().len(), 1);
+		assert_eq!(submit(&server, &connection, &second, share), Err(JOB_NOT_FOUND));
+	}
+
+	#[test]
+	fn connections_are_limited() {
+		let (server, _) = server();
+		let pairs = (0..MAX_CONNECTIONS).map(|_| socket_pair()).collect::<Vec<_>>();
+		for (stream, _) in &pairs {
+			server.connect(stream).unwrap();
+		}
+		let (stream, _miner) = socket_pair();
+		assert!(server.connect(&stream).is_err());
+
+		server.connections.lock().unwrap().pop();
+		assert!(server.connect(&stream).is_ok());
+	}
+}
@@ -0,0 +1,342 @@
+//! Offline simulator of the DAAs in `utxo_runtime::difficulty`. Mines blocks with exponentially
+//! distributed block times at a hashrate that follows a profile, drives the pallet's
+//! `on_finalize` with them in `TestExternalities`, and prints each block's time and difficulty
+//! as CSV on stdout.
+//!
+//! For example, to see how the default DAA copes with an attacker joining and leaving:
+//!
+//!     cargo run -p daa-sim -- --profile on-off --blocks 2000 > on-off.csv
+
+use std::{
+	cell::Cell,
+	cmp::max,
+	io::{self, BufWriter, Write},
+	str::FromStr,
+};
+
+use frame_support::{impl_outer_origin, parameter_types, traits::{Get, OnFinalize, Time}, weights::Weight};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use sp_core::{H256, U256};
+use sp_runtime::{testing::Header, traits::{BlakeTwo256, IdentityLookup}, Perbill};
+use structopt::StructOpt;
+use utxo_runtime::difficulty::{self, Asert, DampClamp, DifficultyAdjustment, DifficultyAndTimestamp, EpochRetarget, Lwma};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "daa-sim", about = "Simulate a difficulty adjustment algorithm and print CSV of block times and difficulties.")]
+struct Opt {
+	/// Number of blocks to mine
+	#[structopt(long, value_name = "COUNT", default_value = "1000")]
+	blocks: u64,
+
+	/// Seed of the random block times
+	#[structopt(long, default_value = "0")]
+	seed: u64,
+
+	/// Difficulty of the first block
+	#[structopt(long, default_value = "4000000")]
+	initial_difficulty: u128,
+
+	#[structopt(flatten)]
+	profile: Profile,
+
+	#[structopt(flatten)]
+	daa: DaaParams,
+}
+
+/// How the hashrate changes over the blocks
+#[derive(Debug, StructOpt)]
+struct Profile {
+	/// Shape of the hashrate: constant, step or on-off
+	#[structopt(long = "profile", value_name = "PROFILE", default_value = "constant")]
+	shape: Shape,
+
+	/// Hashes per second of the miners
+	#[structopt(long, default_value = "1000000")]
+	hashrate: f64,
+
+	/// First block the step profile mines at `--step-hashrate`
+	#[structopt(long, value_name = "BLOCK", default_value = "500")]
+	step_at: u64,
+
+	/// Hashes per second of the miners from `--step-at` on
+	#[structopt(long, default_value = "4000000")]
+	step_hashrate: f64,
+
+	/// Hashes per second the on-off attacker adds while it mines
+	#[structopt(long, default_value = "10000000")]
+	attack_hashrate: f64,
+
+	/// Number of blocks the on-off attacker mines before leaving
+	#[structopt(long, value_name = "COUNT", default_value = "50")]
+	attack_on: u64,
+
+	/// Number of blocks the on-off attacker stays away before mining again
+	#[structopt(long, value_name = "COUNT", default_value = "150")]
+	attack_off: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Shape {
+	/// `hashrate` throughout
+	Constant,
+	/// `hashrate`, then `step_hashrate` from block `step_at`
+	Step,
+	/// `hashrate`, plus `attack_hashrate` for the first `attack_on` blocks of every
+	/// `attack_on + attack_off`
+	OnOff,
+}
+
+impl FromStr for Shape {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"constant" => Ok(Shape::Constant),
+			"step" => Ok(Shape::Step),
+			"on-off" => Ok(Shape::OnOff),
+			_ => Err(format!("Unknown profile {}, expected constant, step or on-off", s)),
+		}
+	}
+}
+
+impl Profile {
+	/// Hashes per second mining block `number`
+	fn hashrate(&self, number: u64) -> f64 {
+		match self.shape {
+			Shape::Constant => self.hashrate,
+			Shape::Step if number >= self.step_at => self.step_hashrate,
+			Shape::Step => self.hashrate,
+			Shape::OnOff if (number - 1) % max(1, self.attack_on + self.attack_off) < self.attack_on =>
+				self.hashrate + self.attack_hashrate,
+			Shape::OnOff => self.hashrate,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Daa {
+	DampClamp,
+	EpochRetarget,
+	Asert,
+	Lwma,
+}
+
+impl FromStr for Daa {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"damp-clamp" => Ok(Daa::DampClamp),
+			"epoch-retarget" => Ok(Daa::EpochRetarget),
+			"asert" => Ok(Daa::Asert),
+			"lwma" => Ok(Daa::Lwma),
+			_ => Err(format!("Unknown DAA {}, expected damp-clamp, epoch-retarget, asert or lwma", s)),
+		}
+	}
+}
+
+/// Parse a window size, interval or half life, which the pallet divides by
+fn at_least_one<T: FromStr + From<u8> + PartialOrd>(s: &str) -> Result<T, String> {
+	match s.parse::<T>() {
+		Ok(value) if value >= T::from(1) => Ok(value),
+		Ok(_) => Err("must be at least 1".into()),
+		Err(_) => Err(format!("{} is not a number", s)),
+	}
+}
+
+/// The DAA and the parameters of the difficulty pallet
+#[derive(Debug, Clone, Copy, StructOpt)]
+struct DaaParams {
+	/// DAA to simulate: damp-clamp, epoch-retarget, asert or lwma
+	#[structopt(long, default_value = "damp-clamp")]
+	daa: Daa,
+
+	/// Block time in milliseconds the DAA aims for
+	#[structopt(long, value_name = "MS", default_value = "3000")]
+	target_block_time: u128,
+
+	/// Dampening factor of damp-clamp
+	#[structopt(long, default_value = "3")]
+	damp_factor: u128,
+
+	/// Clamp factor of damp-clamp
+	#[structopt(long, default_value = "2")]
+	clamp_factor: u128,
+
+	/// Minimum difficulty. The damp factor unless given, as in the runtime.
+	#[structopt(long)]
+	min_difficulty: Option<u128>,
+
+	/// Maximum difficulty. Unbounded unless given.
+	#[structopt(long)]
+	max_difficulty: Option<u128>,
+
+	/// Number of past blocks the DAA is given
+	#[structopt(long, value_name = "COUNT", default_value = "60", parse(try_from_str = at_least_one))]
+	window_size: u32,
+
+	/// Blocks between retargets of epoch-retarget
+	#[structopt(long, value_name = "COUNT", default_value = "100", parse(try_from_str = at_least_one))]
+	retarget_interval: u32,
+
+	/// Milliseconds ahead of or behind schedule for asert to double or halve the difficulty
+	#[structopt(long, value_name = "MS", default_value = "300000", parse(try_from_str = at_least_one))]
+	half_life: u128,
+
+	/// Number of blocks lwma averages over
+	#[structopt(long, value_name = "COUNT", default_value = "45", parse(try_from_str = at_least_one))]
+	lwma_window: u32,
+}
+
+thread_local! {
+	static PARAMS: Cell<Option<DaaParams>> = Cell::new(None);
+	static NOW: Cell<u64> = Cell::new(0);
+}
+
+fn params() -> DaaParams {
+	PARAMS.with(Cell::get).expect("parameters are set before simulating; qed")
+}
+
+/// Implements `Get` with a parameter given on the command line
+macro_rules! param {
+	($name:ident: $type:ty = |$params:ident| $value:expr) => {
+		pub struct $name;
+		impl Get<$type> for $name {
+			fn get() -> $type {
+				let $params = params();
+				$value
+			}
+		}
+	};
+}
+
+param!(TargetBlockTime: u128 = |p| p.target_block_time);
+param!(DampFactor: u128 = |p| p.damp_factor);
+param!(ClampFactor: u128 = |p| p.clamp_factor);
+param!(MinDifficulty: u128 = |p| p.min_difficulty.unwrap_or(p.damp_factor));
+param!(MaxDifficulty: u128 = |p| p.max_difficulty.unwrap_or_else(u128::max_value));
+param!(WindowSize: u32 = |p| p.window_size);
+param!(RetargetInterval: u32 = |p| p.retarget_interval);
+param!(HalfLife: u128 = |p| p.half_life);
+param!(LwmaWindow: u32 = |p| p.lwma_window);
+
+/// Time that only passes as blocks are mined
+pub struct SimTime;
+impl Time for SimTime {
+	type Moment = u64;
+	fn now() -> u64 {
+		NOW.with(Cell::get)
+	}
+}
+
+/// The DAA given on the command line
+pub struct Selected;
+impl DifficultyAdjustment<Sim> for Selected {
+	fn next_difficulty(past: &[Option<DifficultyAndTimestamp<u64>>]) -> U256 {
+		match params().daa {
+			Daa::DampClamp => <DampClamp as DifficultyAdjustment<Sim>>::next_difficulty(past),
+			Daa::EpochRetarget => <EpochRetarget<RetargetInterval> as DifficultyAdjustment<Sim>>::next_difficulty(past),
+			Daa::Asert => <Asert<HalfLife> as DifficultyAdjustment<Sim>>::next_difficulty(past),
+			Daa::Lwma => <Lwma<LwmaWindow> as DifficultyAdjustment<Sim>>::next_difficulty(past),
+		}
+	}
+}
+
+impl_outer_origin! {
+	pub enum Origin for Sim {}
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Sim;
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+}
+impl frame_system::Trait for Sim {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type ModuleToIndex = ();
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl difficulty::Trait for Sim {
+	type TimeProvider = SimTime;
+	type TargetBlockTime = TargetBlockTime;
+	type DampFactor = DampFactor;
+	type ClampFactor = ClampFactor;
+	type MaxDifficulty = MaxDifficulty;
+	type MinDifficulty = MinDifficulty;
+	type Algorithm = Selected;
+	type WindowSize = WindowSize;
+}
+
+type Difficulty = difficulty::Module<Sim>;
+type System = frame_system::Module<Sim>;
+
+fn main() -> io::Result<()> {
+	let opt = Opt::from_args();
+	PARAMS.with(|params| params.set(Some(opt.daa)));
+
+	let mut storage = frame_system::GenesisConfig::default()
+		.build_storage::<Sim>()
+		.expect("the system genesis config builds; qed");
+	storage.top.extend(
+		difficulty::GenesisConfig {
+			initial_difficulty: opt.initial_difficulty.into(),
+			asert_anchor: Default::default(),
+		}
+		.build_storage::<Sim>()
+		.expect("the difficulty genesis config builds; qed")
+		.top,
+	);
+
+	let mut rng = SmallRng::seed_from_u64(opt.seed);
+	let stdout = io::stdout();
+	let mut out = BufWriter::new(stdout.lock());
+	writeln!(out, "block,timestamp,block_time,hashrate,difficulty")?;
+
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		for number in 1..=opt.blocks {
+			let difficulty = Difficulty::difficulty();
+			let hashrate = opt.profile.hashrate(number);
+
+			// Finding a block is a Poisson process, so the time to the next one is exponential
+			// with a mean of the expected number of hashes over the hashrate
+			let mean_block_time = difficulty.low_u128() as f64 / hashrate * 1000.0;
+			let block_time = (-(1.0 - rng.gen::<f64>()).ln() * mean_block_time) as u64;
+			let timestamp = NOW.with(|now| {
+				now.set(now.get().saturating_add(block_time));
+				now.get()
+			});
+
+			System::set_block_number(number);
+			Difficulty::on_finalize(number);
+
+			writeln!(out, "{},{},{},{},{}", number, timestamp, block_time, hashrate, difficulty)?;
+		}
+		Ok(())
+	})
+}
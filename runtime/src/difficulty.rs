@@ -1,15 +1,23 @@
 //! A difficuty adjustment algorithm (DAA) to keep the block time close to a particular goal
 //! Cribbed from Kulupu https://github.com/kulupu/kulupu/blob/master/runtime/src/difficulty.rs
 //!
-//! It is possible to implement other DAAs such as that of BTC and BCH. This would be an interesting
-//! and worth-while experiment. The DAAs should be abstracted away with a trait.
+//! The DAA is abstracted away with the `DifficultyAdjustment` trait, so others such as those of BTC
+//! and BCH can be experimented with by implementing it and choosing it in the runtime.
 //! Some ideas: https://papers.ssrn.com/sol3/papers.cfm?abstract_id=3410460
 
-use core::cmp::{min, max};
+use core::{cmp::{min, max}, marker::PhantomData};
 use sp_runtime::traits::UniqueSaturatedInto;
-use frame_support::{decl_storage, decl_module, traits::{Get, Time}};
+use frame_support::{
+	decl_storage, decl_module,
+	storage::{migration::take_storage_value, StoragePrefixedMap},
+	traits::{Get, Time},
+	weights::Weight,
+};
 use codec::{Encode, Decode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
 use sp_core::U256;
+use sp_std::vec::Vec;
 
 #[derive(Encode, Decode, Clone, Copy, Eq, PartialEq, Debug)]
 pub struct DifficultyAndTimestamp<M> {
@@ -45,86 +53,920 @@ pub trait Trait: frame_system::Trait {
 	/// avoids getting stuck when trying to increase difficulty subject to dampening
 	/// Recommended to use same value as DampFactor
 	type MinDifficulty: Get<u128>;
+	/// The DAA computing the difficulty of the next block. Recommended: `DampClamp`
+	type Algorithm: DifficultyAdjustment<Self>;
+	/// Number of past blocks whose difficulty and timestamp the DAA is given. Recommended: 60
+	/// Zero counts as 1. Changing it takes effect at the next runtime upgrade, so it needs a
+	/// `spec_version` bump.
+	type WindowSize: Get<u32>;
 }
 
-const DIFFICULTY_ADJUST_WINDOW: u128 = 60;
+/// Number of past blocks `PastDifficultiesAndTimestamps` held before `Releases::V2_0_0`
+pub(crate) const LEGACY_WINDOW_SIZE: usize = 60;
 type Difficulty = U256;
+type MomentOf<T> = <<T as Trait>::TimeProvider as Time>::Moment;
+
+/// A difficulty adjustment algorithm
+pub trait DifficultyAdjustment<T: Trait> {
+	/// Difficulty of the next block, given the difficulties and timestamps of the past blocks,
+	/// from earliest to latest. The latest is the block being finalized, and blocks from before
+	/// genesis are `None`.
+	fn next_difficulty(past: &[Option<DifficultyAndTimestamp<MomentOf<T>>>]) -> Difficulty;
+}
+
+/// Kulupu's DAA. Scales the average difficulty of the window by how far its average block time
+/// is from the target, after damping that toward the target and clamping it around the target.
+pub struct DampClamp;
+
+impl<T: Trait> DifficultyAdjustment<T> for DampClamp {
+	fn next_difficulty(past: &[Option<DifficultyAndTimestamp<MomentOf<T>>>]) -> Difficulty {
+		let mut ts_delta = 0;
+		for i in 1..past.len() {
+			let prev: Option<u128> = past[i - 1].map(|d| d.timestamp.unique_saturated_into());
+			let cur: Option<u128> = past[i].map(|d| d.timestamp.unique_saturated_into());
+
+			let delta = match (prev, cur) {
+				(Some(prev), Some(cur)) => cur.saturating_sub(prev),
+				_ => T::TargetBlockTime::get(),
+			};
+			ts_delta += delta;
+		}
+
+		if ts_delta == 0 {
+			ts_delta = 1;
+		}
+
+		let mut diff_sum = U256::zero();
+		for entry in past {
+			let diff = match entry.map(|d| d.difficulty) {
+				Some(diff) => diff,
+				None => InitialDifficulty::get(),
+			};
+			diff_sum += diff;
+		}
+
+		if diff_sum < U256::from(T::MinDifficulty::get()) {
+			diff_sum = U256::from(T::MinDifficulty::get());
+		}
+
+		// Calculate the average length of the adjustment window
+		let adjustment_window = past.len() as u128 * T::TargetBlockTime::get();
+
+		// adjust time delta toward goal subject to dampening and clamping
+		let adj_ts = clamp(
+			damp(ts_delta, adjustment_window, T::DampFactor::get()),
+			adjustment_window,
+			T::ClampFactor::get(),
+		);
+
+		// minimum difficulty avoids getting stuck due to dampening
+		min(U256::from(T::MaxDifficulty::get()),
+			max(U256::from(T::MinDifficulty::get()),
+				diff_sum * U256::from(T::TargetBlockTime::get()) / U256::from(adj_ts)))
+	}
+}
+
+/// Bitcoin's DAA. The difficulty only changes once every `Interval` blocks, when it is scaled by how
+/// far the time those blocks took is from the target, clamped to within 4x of the target.
+/// `Interval` must be at least 1.
+pub struct EpochRetarget<Interval>(PhantomData<Interval>);
+
+/// Largest factor `EpochRetarget` changes the difficulty by at once
+const RETARGET_CLAMP_FACTOR: u128 = 4;
+
+impl<T: Trait, Interval: Get<u32>> DifficultyAdjustment<T> for EpochRetarget<Interval> {
+	fn next_difficulty(past: &[Option<DifficultyAndTimestamp<MomentOf<T>>>]) -> Difficulty {
+		let latest = match past.last() {
+			Some(Some(latest)) => *latest,
+			_ => return <CurrentDifficulty>::get(),
+		};
+		let number = <frame_system::Module<T>>::block_number();
+
+		// The first epoch starts at the first block finalized with this DAA
+		let (start_number, start_time) = match <RetargetEpochStart<T>>::get() {
+			Some(start) => start,
+			None => {
+				<RetargetEpochStart<T>>::put((number, latest.timestamp));
+				return latest.difficulty
+			}
+		};
+		if number < start_number + Interval::get().into() {
+			return latest.difficulty
+		}
+		<RetargetEpochStart<T>>::put((number, latest.timestamp));
+
+		let now: u128 = latest.timestamp.unique_saturated_into();
+		let start: u128 = start_time.unique_saturated_into();
+		let expected = u128::from(Interval::get()) * T::TargetBlockTime::get();
+		let elapsed = max(1, clamp(now.saturating_sub(start), expected, RETARGET_CLAMP_FACTOR));
+
+		min(U256::from(T::MaxDifficulty::get()),
+			max(U256::from(T::MinDifficulty::get()),
+				latest.difficulty * U256::from(expected) / U256::from(elapsed)))
+	}
+}
+
+/// Zawy's LWMA DAA. Scales the average difficulty of the last `Window` blocks by how far their
+/// solve times, weighted linearly from the oldest to the latest, are from the target.
+/// Solve times are clamped to between 1 millisecond and 6 target block times, and timestamps
+/// earlier than one before them count as one millisecond after it, so a miner cannot move the
+/// difficulty far by lying about time. `Window` must be at least 1, and is limited by
+/// `WindowSize`.
+pub struct Lwma<Window>(PhantomData<Window>);
+
+/// Largest multiple of the target block time a solve time counts as in `Lwma`
+const LWMA_MAX_SOLVE_TIMES: u128 = 6;
+
+impl<T: Trait, Window: Get<u32>> DifficultyAdjustment<T> for Lwma<Window> {
+	fn next_difficulty(past: &[Option<DifficultyAndTimestamp<MomentOf<T>>>]) -> Difficulty {
+		// The window's blocks plus the one before, whose timestamp the first solve time is from
+		let blocks = past.iter().flatten().rev().take(Window::get() as usize + 1).collect::<Vec<_>>();
+		let latest = match blocks.first() {
+			Some(latest) => latest.difficulty,
+			None => return <CurrentDifficulty>::get(),
+		};
+		if blocks.len() < 2 {
+			return latest
+		}
+
+		let target = T::TargetBlockTime::get();
+		let n = blocks.len() as u128 - 1;
+		let mut previous: u128 = blocks[n as usize].timestamp.unique_saturated_into();
+		let mut weighted_solve_times = 0;
+		let mut diff_sum = U256::zero();
+		for (weight, block) in blocks.iter().rev().skip(1).enumerate() {
+			let timestamp: u128 = block.timestamp.unique_saturated_into();
+			let timestamp = max(timestamp, previous + 1);
+			let solve_time = min(timestamp - previous, LWMA_MAX_SOLVE_TIMES * target);
+			previous = timestamp;
+
+			weighted_solve_times += (weight as u128 + 1) * solve_time;
+			diff_sum += block.difficulty;
+		}
+		// Keep the weighted solve times sensible in case of strange ones
+		let weighted_solve_times = max(weighted_solve_times, n * n * target / 20);
+
+		// The average difficulty, over the weighted average solve time divided by the target
+		min(U256::from(T::MaxDifficulty::get()),
+			max(U256::from(T::MinDifficulty::get()),
+				diff_sum * U256::from(target) * U256::from(n + 1)
+					/ U256::from(2 * weighted_solve_times)))
+	}
+}
+
+/// The block `Asert` schedules difficulties from
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(Encode, Decode, Clone, Copy, Default, Eq, PartialEq, Debug)]
+pub struct AsertAnchor {
+	/// Number of the anchor block
+	pub height: u64,
+	/// Timestamp of the anchor block
+	pub timestamp: u64,
+	/// Difficulty of the anchor block, which the block after it is mined at too, as it is on
+	/// schedule. Zero until there is an anchor.
+	pub difficulty: Difficulty,
+}
+
+/// Bitcoin Cash's aserti3-2d DAA. The difficulty doubles for every `HalfLife` milliseconds the
+/// chain is ahead of the schedule of one block per target block time since the anchor block, and
+/// halves for every `HalfLife` it is behind. Without an anchor in the genesis config, the first
+/// block finalized with this DAA becomes the anchor.
+/// `HalfLife` must be at least 1.
+pub struct Asert<HalfLife>(PhantomData<HalfLife>);
+
+impl<T: Trait, HalfLife: Get<u128>> DifficultyAdjustment<T> for Asert<HalfLife> {
+	fn next_difficulty(past: &[Option<DifficultyAndTimestamp<MomentOf<T>>>]) -> Difficulty {
+		let latest = match past.last() {
+			Some(Some(latest)) => *latest,
+			_ => return <CurrentDifficulty>::get(),
+		};
+		let height = UniqueSaturatedInto::<u64>::unique_saturated_into(<frame_system::Module<T>>::block_number());
+		let now = UniqueSaturatedInto::<u64>::unique_saturated_into(latest.timestamp);
+
+		let anchor = <AsertAnchorBlock>::get();
+		if anchor.difficulty.is_zero() {
+			<AsertAnchorBlock>::put(AsertAnchor { height, timestamp: now, difficulty: latest.difficulty });
+			return latest.difficulty
+		}
+
+		let height_delta = i128::from(height) - i128::from(anchor.height);
+		let time_delta = i128::from(now) - i128::from(anchor.timestamp);
+		let ahead = T::TargetBlockTime::get() as i128 * height_delta - time_delta;
+
+		// 2^(ahead / half life) in 16.16 fixed point, as 2^shifts * factor / 2^16
+		let exponent = ahead.saturating_mul(1 << 16) / HalfLife::get() as i128;
+		let shifts = exponent >> 16;
+		let frac = (exponent & 0xffff) as u128;
+		// Cubic approximation of 2^frac for frac in [0, 1)
+		let factor = 65536 + ((195_766_423_245_049 * frac
+			+ 971_821_376 * frac * frac
+			+ 5127 * frac * frac * frac
+			+ (1 << 47)) >> 48);
+
+		let scaled = anchor.difficulty * U256::from(factor);
+		let shifts = shifts - 16;
+		let difficulty = if shifts >= 0 {
+			if i128::from(scaled.bits() as u32) + shifts > 256 {
+				U256::max_value()
+			} else {
+				scaled << shifts as usize
+			}
+		} else if shifts <= -256 {
+			U256::zero()
+		} else {
+			scaled >> (-shifts) as usize
+		};
+
+		min(U256::from(T::MaxDifficulty::get()), max(U256::from(T::MinDifficulty::get()), difficulty))
+	}
+}
+
+// A value placed in storage that represents the current version of the Difficulty storage.
+// This value is used by the `on_runtime_upgrade` logic to determine whether we run
+// storage migration logic.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Releases {
+	/// `PastDifficultiesAndTimestamps` is an array of the last 60 blocks
+	V1_0_0,
+	/// `PastDifficultiesAndTimestamps` is a ring buffer of the last `WindowSize` blocks
+	V2_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
+}
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Difficulty {
-		/// Past difficulties and timestamps, from earliest to latest.
+		/// Past difficulties and timestamps, as a ring buffer of `PastWindow` slots of which
+		/// `PastHead` is the earliest.
 		PastDifficultiesAndTimestamps:
-		[Option<DifficultyAndTimestamp<<<T as Trait>::TimeProvider as Time>::Moment>>; 60]
-			= [None; DIFFICULTY_ADJUST_WINDOW as usize];
+			map hasher(twox_64_concat) u32 => Option<DifficultyAndTimestamp<MomentOf<T>>>;
+		/// Slot of `PastDifficultiesAndTimestamps` the next block is written to.
+		PastHead: u32;
+		/// Number of slots of `PastDifficultiesAndTimestamps`.
+		PastWindow build(|_: &GenesisConfig| Module::<T>::window_size()): u32;
 		/// Current difficulty.
 		pub CurrentDifficulty get(fn difficulty) build(|config: &GenesisConfig| {
 			config.initial_difficulty
 		}): Difficulty;
 		/// Initial difficulty.
 		pub InitialDifficulty config(initial_difficulty): Difficulty;
+		/// Number and timestamp of the block the current `EpochRetarget` epoch started at.
+		RetargetEpochStart: Option<(T::BlockNumber, MomentOf<T>)>;
+		/// Anchor block of `Asert`.
+		pub AsertAnchorBlock get(fn asert_anchor) config(asert_anchor): AsertAnchor;
+
+		/// Storage version of the pallet.
+		///
+		/// This is set to v2.0.0 for new networks.
+		StorageVersion build(|_: &GenesisConfig| Releases::V2_0_0): Releases;
 	}
 }
 
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
-		fn on_finalize(_n: T::BlockNumber) {
-			let mut data = PastDifficultiesAndTimestamps::<T>::get();
-
-			for i in 1..data.len() {
-				data[i - 1] = data[i];
+		fn on_runtime_upgrade() -> Weight {
+			let mut weight = 0;
+			if StorageVersion::get() == Releases::V1_0_0 {
+				weight += Self::migrate_to_ring_buffer();
 			}
+			if PastWindow::get() != Self::window_size() {
+				weight += Self::resize_window();
+			}
+			weight
+		}
+
+		fn on_initialize(_n: T::BlockNumber) -> Weight {
+			// `on_finalize` reads every slot of the window, the ring buffer's position and size,
+			// the current and initial difficulty, the time and the state of the DAA, and writes
+			// a slot, the position, the difficulty and the state of the DAA
+			T::DbWeight::get().reads_writes(Self::past_window() as Weight + 6, 4)
+		}
 
-			data[data.len() - 1] = Some(DifficultyAndTimestamp {
+		fn on_finalize(_n: T::BlockNumber) {
+			let head = PastHead::get();
+			<PastDifficultiesAndTimestamps<T>>::insert(head, DifficultyAndTimestamp {
 				timestamp: T::TimeProvider::now(),
 				difficulty: Self::difficulty(),
 			});
+			PastHead::put((head + 1) % Self::past_window());
+
+			let difficulty = T::Algorithm::next_difficulty(&Self::past_difficulties_and_timestamps());
+			<CurrentDifficulty>::put(difficulty);
+		}
+	}
+}
+
+impl<T: Trait> Module<T> {
+	/// Past difficulties and timestamps, from earliest to latest. Blocks from before genesis
+	/// are `None`.
+	pub fn past_difficulties_and_timestamps() -> Vec<Option<DifficultyAndTimestamp<MomentOf<T>>>> {
+		let window = Self::past_window();
+		let head = PastHead::get();
+		(0..window).map(|i| <PastDifficultiesAndTimestamps<T>>::get((head + i) % window)).collect()
+	}
+
+	/// Average time between the past blocks, if there are two or more
+	pub fn average_block_time() -> Option<u64> {
+		let past = Self::past_difficulties_and_timestamps().into_iter().flatten().collect::<Vec<_>>();
+		let (first, last) = match (past.first(), past.last()) {
+			(Some(first), Some(last)) if past.len() > 1 => (first, last),
+			_ => return None,
+		};
+		let elapsed = UniqueSaturatedInto::<u64>::unique_saturated_into(last.timestamp)
+			.saturating_sub(UniqueSaturatedInto::<u64>::unique_saturated_into(first.timestamp));
+		Some(elapsed / (past.len() as u64 - 1))
+	}
+
+	/// Hashes per second the network is estimated to make: the expected number of hashes to
+	/// find the past blocks after the first, which is their difficulty, over the time they
+	/// took. `None` with fewer than two past blocks, or if they took no time.
+	/// Assumes the `TimeProvider` counts milliseconds.
+	pub fn network_hashrate() -> Option<U256> {
+		let past = Self::past_difficulties_and_timestamps().into_iter().flatten().collect::<Vec<_>>();
+		let (first, last) = match (past.first(), past.last()) {
+			(Some(first), Some(last)) if past.len() > 1 => (first, last),
+			_ => return None,
+		};
+		let elapsed = UniqueSaturatedInto::<u128>::unique_saturated_into(last.timestamp)
+			.saturating_sub(UniqueSaturatedInto::<u128>::unique_saturated_into(first.timestamp));
+		if elapsed == 0 {
+			return None
+		}
+		let work = past.iter().skip(1).fold(U256::zero(), |work, block| work.saturating_add(block.difficulty));
+		Some(work * U256::from(1_000) / U256::from(elapsed))
+	}
+
+	/// `WindowSize`, at least 1
+	fn window_size() -> u32 {
+		max(1, T::WindowSize::get())
+	}
+
+	/// `PastWindow`, at least 1 even before the ring buffer is laid out
+	fn past_window() -> u32 {
+		max(1, PastWindow::get())
+	}
+
+	/// Lay `PastDifficultiesAndTimestamps` out afresh for `WindowSize` slots, keeping the latest
+	/// of `past`, from earliest to latest.
+	fn put_past(past: Vec<Option<DifficultyAndTimestamp<MomentOf<T>>>>) -> Weight {
+		let window = Self::window_size() as usize;
+		let kept = min(window, past.len());
+		let missing = window - kept;
+		let dropped = past.len() - kept;
+
+		<PastDifficultiesAndTimestamps<T>>::remove_all();
+		for (slot, entry) in past.into_iter().skip(dropped).enumerate() {
+			if let Some(entry) = entry {
+				<PastDifficultiesAndTimestamps<T>>::insert((missing + slot) as u32, entry);
+			}
+		}
+		PastHead::put(0);
+		PastWindow::put(window as u32);
+
+		T::DbWeight::get().writes(window as Weight + 3)
+	}
+
+	/// Move the past blocks from the array of `Releases::V1_0_0` into the ring buffer
+	fn migrate_to_ring_buffer() -> Weight {
+		let legacy = take_storage_value::<[Option<DifficultyAndTimestamp<MomentOf<T>>>; LEGACY_WINDOW_SIZE]>(
+			b"Difficulty",
+			b"PastDifficultiesAndTimestamps",
+			&[],
+		).unwrap_or([None; LEGACY_WINDOW_SIZE]);
+		let weight = Self::put_past(legacy.to_vec());
+		StorageVersion::put(Releases::V2_0_0);
+
+		weight + T::DbWeight::get().reads_writes(1, 2)
+	}
+
+	/// Move the past blocks into a ring buffer of the new `WindowSize`
+	fn resize_window() -> Weight {
+		let window = PastWindow::get();
+		let weight = Self::put_past(Self::past_difficulties_and_timestamps());
+
+		weight + T::DbWeight::get().reads(window as Weight + 2)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use std::cell::Cell;
+	use rand::{rngs::SmallRng, Rng, SeedableRng};
+	use frame_support::{impl_outer_origin, parameter_types, traits::{OnFinalize, OnRuntimeUpgrade}, weights::Weight};
+	use sp_core::H256;
+	use sp_runtime::{testing::Header, traits::{BlakeTwo256, IdentityLookup}, Perbill};
+
+	impl_outer_origin! {
+		pub enum Origin for Test {}
+	}
+
+	#[derive(Clone, Eq, PartialEq)]
+	pub struct Test;
+	parameter_types! {
+			pub const BlockHashCount: u64 = 250;
+			pub const MaximumBlockWeight: Weight = 1024;
+			pub const MaximumBlockLength: u32 = 2 * 1024;
+			pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+	}
+	impl frame_system::Trait for Test {
+		type BaseCallFilter = ();
+		type Origin = Origin;
+		type Call = ();
+		type Index = u64;
+		type BlockNumber = u64;
+		type Hash = H256;
+		type Hashing = BlakeTwo256;
+		type AccountId = u64;
+		type Lookup = IdentityLookup<Self::AccountId>;
+		type Header = Header;
+		type Event = ();
+		type BlockHashCount = BlockHashCount;
+		type MaximumBlockWeight = MaximumBlockWeight;
+		type DbWeight = ();
+		type BlockExecutionWeight = ();
+		type ExtrinsicBaseWeight = ();
+		type MaximumExtrinsicWeight = MaximumBlockWeight;
+		type MaximumBlockLength = MaximumBlockLength;
+		type AvailableBlockRatio = AvailableBlockRatio;
+		type Version = ();
+		type ModuleToIndex = ();
+		type AccountData = ();
+		type OnNewAccount = ();
+		type OnKilledAccount = ();
+		type SystemWeightInfo = ();
+	}
+
+	// The DAA the test runtime uses
+	#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+	enum Daa {
+		DampClamp,
+		EpochRetarget,
+		Asert,
+		Lwma,
+	}
+
+	thread_local! {
+		static NOW: Cell<u64> = Cell::new(0);
+		static DAA: Cell<Daa> = Cell::new(Daa::DampClamp);
+		static WINDOW: Cell<u32> = Cell::new(60);
+	}
+
+	// Window size a test can change, as a runtime upgrade would
+	pub struct WindowSize;
+	impl Get<u32> for WindowSize {
+		fn get() -> u32 {
+			WINDOW.with(Cell::get)
+		}
+	}
+
+	// Time only passes when a test says so
+	pub struct MockTime;
+	impl Time for MockTime {
+		type Moment = u64;
+		fn now() -> u64 {
+			NOW.with(Cell::get)
+		}
+	}
+
+	parameter_types! {
+			pub const TargetBlockTime: u128 = 3_000;
+			pub const DampFactor: u128 = 3;
+			pub const ClampFactor: u128 = 2;
+			pub const MaxDifficulty: u128 = u128::max_value();
+			pub const RetargetInterval: u32 = 10;
+			pub const HalfLife: u128 = 50 * 3_000;
+			pub const LwmaWindow: u32 = 45;
+	}
+
+	// Whichever DAA the test picked
+	pub struct Selected;
+	impl DifficultyAdjustment<Test> for Selected {
+		fn next_difficulty(past: &[Option<DifficultyAndTimestamp<u64>>]) -> Difficulty {
+			match DAA.with(Cell::get) {
+				Daa::DampClamp => <DampClamp as DifficultyAdjustment<Test>>::next_difficulty(past),
+				Daa::EpochRetarget => <EpochRetarget<RetargetInterval> as DifficultyAdjustment<Test>>::next_difficulty(past),
+				Daa::Asert => <Asert<HalfLife> as DifficultyAdjustment<Test>>::next_difficulty(past),
+				Daa::Lwma => <Lwma<LwmaWindow> as DifficultyAdjustment<Test>>::next_difficulty(past),
+			}
+		}
+	}
+	impl Trait for Test {
+		type TimeProvider = MockTime;
+		type TargetBlockTime = TargetBlockTime;
+		type DampFactor = DampFactor;
+		type ClampFactor = ClampFactor;
+		type MaxDifficulty = MaxDifficulty;
+		type MinDifficulty = DampFactor;
+		type Algorithm = Selected;
+		type WindowSize = WindowSize;
+	}
+
+	type DifficultyModule = Module<Test>;
+	type System = frame_system::Module<Test>;
+
+	const INITIAL_DIFFICULTY: u64 = 100_000;
+
+	// Reset the mocks, which tests on the same thread share
+	fn reset_mocks(daa: Daa) {
+		NOW.with(|now| now.set(0));
+		DAA.with(|selected| selected.set(daa));
+		WINDOW.with(|window| window.set(60));
+	}
+
+	fn new_test_ext(daa: Daa) -> sp_io::TestExternalities {
+		reset_mocks(daa);
+
+		let mut t = frame_system::GenesisConfig::default()
+			.build_storage::<Test>()
+			.unwrap();
+
+		t.top.extend(
+			GenesisConfig {
+				initial_difficulty: INITIAL_DIFFICULTY.into(),
+				asert_anchor: Default::default(),
+			}
+			.build_storage::<Test>()
+			.unwrap()
+			.top,
+		);
+
+		t.into()
+	}
+
+	// Finalize the `blocks` blocks after `System::block_number()`, each found after exactly the
+	// expected time at `hashrate` hashes per millisecond
+	fn mine(blocks: u64, hashrate: u64) {
+		for _ in 0..blocks {
+			let block_time = DifficultyModule::difficulty() / U256::from(hashrate);
+			finalize_after(block_time.as_u64());
+		}
+	}
+
+	// Finalize the block after `System::block_number()`, found `block_time` milliseconds after it
+	fn finalize_after(block_time: u64) {
+		finalize_at(MockTime::now() + block_time);
+	}
+
+	// Finalize the block after `System::block_number()`, with timestamp `timestamp`
+	fn finalize_at(timestamp: u64) {
+		NOW.with(|now| now.set(timestamp));
+
+		let number = System::block_number() + 1;
+		System::set_block_number(number);
+		DifficultyModule::on_finalize(number);
+	}
+
+	// Hashrate in hashes per millisecond that finds blocks at the target block time at the
+	// initial difficulty
+	const BASE_HASHRATE: f64 = INITIAL_DIFFICULTY as f64 / 3_000.0;
+
+	// Random hashrate swings, as the number of blocks and the hashrate of each period
+	fn hashrate_swings(rng: &mut SmallRng) -> Vec<(u64, f64)> {
+		(0..10)
+			.map(|_| (rng.gen_range(50, 300), BASE_HASHRATE * 2f64.powi(rng.gen_range(-2, 3))))
+			.collect()
+	}
+
+	// Mine through `swings` with `daa`, each block found after an exponentially distributed time.
+	// Returns the block times and how many halvings or doublings each difficulty was away from
+	// the one that suited the hashrate.
+	fn simulate(daa: Daa, swings: &[(u64, f64)], seed: u64) -> (Vec<u64>, Vec<f64>) {
+		let mut rng = SmallRng::seed_from_u64(seed);
+		let mut block_times = Vec::new();
+		let mut errors = Vec::new();
+		new_test_ext(daa).execute_with(|| {
+			for &(blocks, hashrate) in swings {
+				for _ in 0..blocks {
+					let difficulty = DifficultyModule::difficulty().low_u128() as f64;
+					errors.push((difficulty / (hashrate * 3_000.0)).log2());
+
+					let block_time = (-(1.0 - rng.gen::<f64>()).ln() * difficulty / hashrate) as u64;
+					block_times.push(block_time);
+					finalize_after(block_time);
+				}
+			}
+		});
+		(block_times, errors)
+	}
+
+	fn mean(values: impl Iterator<Item = f64>) -> f64 {
+		let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+		sum / count as f64
+	}
+
+	#[test]
+	fn epoch_retarget_keeps_difficulty_within_an_epoch() {
+		new_test_ext(Daa::EpochRetarget).execute_with(|| {
+			mine(10, 1_000);
+			assert_eq!(DifficultyModule::difficulty(), U256::from(INITIAL_DIFFICULTY));
+		});
+	}
+
+	#[test]
+	fn epoch_retarget_clamps_to_4x() {
+		new_test_ext(Daa::EpochRetarget).execute_with(|| {
+			// Blocks are found 30 times faster than the target
+			mine(11, 1_000);
+			assert_eq!(DifficultyModule::difficulty(), U256::from(4 * INITIAL_DIFFICULTY));
+		});
+	}
+
+	#[test]
+	fn epoch_retarget_converges_to_target_block_time() {
+		new_test_ext(Daa::EpochRetarget).execute_with(|| {
+			// Retargets at blocks 11, 21, ... until the difficulty suits 1000 hashes per millisecond
+			mine(91, 1_000);
+			assert_eq!(DifficultyModule::difficulty(), U256::from(3_000_000));
+
+			// Half the hashrate leaves, so blocks take twice the target until the next retarget
+			mine(10, 500);
+			assert_eq!(DifficultyModule::difficulty(), U256::from(1_500_000));
+
+			let start = MockTime::now();
+			mine(10, 500);
+			assert_eq!(MockTime::now() - start, 10 * 3_000);
+		});
+	}
+
+	#[test]
+	fn asert_anchors_at_first_block() {
+		new_test_ext(Daa::Asert).execute_with(|| {
+			finalize_after(1_000);
+			assert_eq!(DifficultyModule::asert_anchor(), AsertAnchor {
+				height: 1,
+				timestamp: 1_000,
+				difficulty: INITIAL_DIFFICULTY.into(),
+			});
 
-			let mut ts_delta = 0;
-			for i in 1..(DIFFICULTY_ADJUST_WINDOW as usize) {
-				let prev: Option<u128> = data[i - 1].map(|d| d.timestamp.unique_saturated_into());
-				let cur: Option<u128> = data[i].map(|d| d.timestamp.unique_saturated_into());
+			// Blocks on schedule keep the difficulty exactly
+			for _ in 0..20 {
+				finalize_after(3_000);
+			}
+			assert_eq!(DifficultyModule::difficulty(), U256::from(INITIAL_DIFFICULTY));
+		});
+	}
+
+	#[test]
+	fn asert_doubles_per_half_life_ahead() {
+		new_test_ext(Daa::Asert).execute_with(|| {
+			finalize_after(1_000);
+			// 50 blocks in no time are one half life ahead of schedule
+			for _ in 0..50 {
+				finalize_after(0);
+			}
+			assert_eq!(DifficultyModule::difficulty(), U256::from(2 * INITIAL_DIFFICULTY));
 
-				let delta = match (prev, cur) {
-					(Some(prev), Some(cur)) => cur.saturating_sub(prev),
-					_ => T::TargetBlockTime::get(),
-				};
-				ts_delta += delta;
+			// Then half a half life more
+			for _ in 0..25 {
+				finalize_after(0);
 			}
+			let expected = 2.0 * 2f64.sqrt() * INITIAL_DIFFICULTY as f64;
+			let difficulty = DifficultyModule::difficulty().low_u128() as f64;
+			assert!((difficulty / expected - 1.0).abs() < 1e-4);
+		});
+	}
+
+	#[test]
+	fn asert_halves_per_half_life_behind() {
+		new_test_ext(Daa::Asert).execute_with(|| {
+			finalize_after(1_000);
+			finalize_after(3_000 + HalfLife::get() as u64);
+			assert_eq!(DifficultyModule::difficulty(), U256::from(INITIAL_DIFFICULTY / 2));
+		});
+	}
+
+	// Difficulty `Asert` gives the block after the one `blocks` blocks after `anchor`, if that
+	// one is `ahead` milliseconds ahead of schedule
+	fn asert_difficulty(anchor: AsertAnchor, blocks: u64, ahead: i64) -> U256 {
+		AsertAnchorBlock::put(anchor);
+		System::set_block_number(anchor.height + blocks);
+		let timestamp = (anchor.timestamp as i64 + blocks as i64 * 3_000 - ahead) as u64;
+		let past = [Some(DifficultyAndTimestamp { difficulty: anchor.difficulty, timestamp })];
+		<Asert<HalfLife> as DifficultyAdjustment<Test>>::next_difficulty(&past)
+	}
+
+	fn ratio(numerator: U256, denominator: U256) -> f64 {
+		numerator.low_u128() as f64 / denominator.low_u128() as f64
+	}
+
+	fn random_anchor(rng: &mut SmallRng) -> AsertAnchor {
+		AsertAnchor {
+			height: rng.gen_range(1, 1_000_000),
+			timestamp: rng.gen_range(1 << 40, 1 << 41),
+			difficulty: rng.gen_range(1_000_000_000u64, 1 << 60).into(),
+		}
+	}
 
-			if ts_delta == 0 {
-				ts_delta = 1;
+	#[test]
+	fn asert_doubles_and_halves_per_half_life_from_any_anchor() {
+		let half_life = HalfLife::get() as i64;
+		let mut rng = SmallRng::seed_from_u64(0);
+		new_test_ext(Daa::Asert).execute_with(|| {
+			for _ in 0..1_000 {
+				let anchor = random_anchor(&mut rng);
+				let blocks = rng.gen_range(1, 100_000);
+				let ahead = rng.gen_range(-4 * half_life, 4 * half_life);
+				let difficulty = asert_difficulty(anchor, blocks, ahead);
+
+				let doubled = asert_difficulty(anchor, blocks, ahead + half_life);
+				assert!((ratio(doubled, difficulty) - 2.0).abs() < 1e-4, "{:?} {} {}", anchor, blocks, ahead);
+				let halved = asert_difficulty(anchor, blocks, ahead - half_life);
+				assert!((ratio(halved, difficulty) - 0.5).abs() < 1e-4, "{:?} {} {}", anchor, blocks, ahead);
+
+				// On schedule, the anchor's difficulty is kept exactly
+				assert_eq!(asert_difficulty(anchor, blocks, 0), anchor.difficulty);
 			}
+		});
+	}
 
-			let mut diff_sum = U256::zero();
-			for i in 0..(DIFFICULTY_ADJUST_WINDOW as usize) {
-				let diff = match data[i].map(|d| d.difficulty) {
-					Some(diff) => diff,
-					None => InitialDifficulty::get(),
-				};
-				diff_sum += diff;
+	#[test]
+	fn asert_difficulty_rises_the_further_ahead_of_schedule() {
+		let half_life = HalfLife::get() as i64;
+		let mut rng = SmallRng::seed_from_u64(0);
+		new_test_ext(Daa::Asert).execute_with(|| {
+			for _ in 0..100 {
+				let anchor = random_anchor(&mut rng);
+				let blocks = rng.gen_range(1, 100_000);
+				let mut aheads: Vec<i64> = (0..100).map(|_| rng.gen_range(-4 * half_life, 4 * half_life)).collect();
+				aheads.sort();
+				let difficulties: Vec<U256> = aheads.iter().map(|&ahead| asert_difficulty(anchor, blocks, ahead)).collect();
+				for (pair, at) in difficulties.windows(2).zip(aheads.windows(2)) {
+					assert!(pair[0] <= pair[1], "{:?} {}: {:?} at {:?}", anchor, blocks, pair, at);
+				}
 			}
+		});
+	}
+
+	#[test]
+	fn asert_keeps_mean_block_time_near_target_through_hashrate_swings() {
+		for seed in 0..20 {
+			let swings = hashrate_swings(&mut SmallRng::seed_from_u64(seed));
+			let (block_times, _) = simulate(Daa::Asert, &swings, seed);
+			let mean_block_time = mean(block_times.iter().map(|&time| time as f64));
+			assert!((mean_block_time / 3_000.0 - 1.0).abs() < 0.1, "seed {}: {}", seed, mean_block_time);
+		}
+	}
 
-			if diff_sum < U256::from(T::MinDifficulty::get()) {
-				diff_sum = U256::from(T::MinDifficulty::get());
+	#[test]
+	fn asert_tracks_hashrate_swings_closer_than_damp_clamp() {
+		for seed in 0..20 {
+			let swings = hashrate_swings(&mut SmallRng::seed_from_u64(seed));
+			let rms_error = |daa| {
+				let (_, errors) = simulate(daa, &swings, seed);
+				mean(errors.iter().map(|error| error * error)).sqrt()
+			};
+			let asert = rms_error(Daa::Asert);
+			let damp_clamp = rms_error(Daa::DampClamp);
+			assert!(asert < damp_clamp, "seed {}: {} >= {}", seed, asert, damp_clamp);
+		}
+	}
+
+	#[test]
+	fn lwma_converges_to_target_block_time() {
+		new_test_ext(Daa::Lwma).execute_with(|| {
+			// Blocks come 10 times too fast, then 4 times too slow
+			for &hashrate in [333, 8].iter() {
+				mine(300, hashrate);
+				let expected = hashrate as f64 * 3_000.0;
+				let difficulty = DifficultyModule::difficulty().low_u128() as f64;
+				assert!((difficulty / expected - 1.0).abs() < 0.01, "{} != {}", difficulty, expected);
 			}
+		});
+	}
+
+	#[test]
+	fn lwma_clamps_manipulated_timestamps() {
+		new_test_ext(Daa::Lwma).execute_with(|| {
+			mine(100, 33);
+			let settled = DifficultyModule::difficulty().low_u128() as f64;
 
-			// Calculate the average length of the adjustment window
-			let adjustment_window = DIFFICULTY_ADJUST_WINDOW * T::TargetBlockTime::get();
+			// A block claiming to be an hour late counts as 6 block times late
+			finalize_after(3_600_000);
+			let difficulty = DifficultyModule::difficulty().low_u128() as f64;
+			assert!(difficulty > 0.75 * settled, "{} dropped from {}", difficulty, settled);
 
-			// adjust time delta toward goal subject to dampening and clamping
-			let adj_ts = clamp(
-				damp(ts_delta, adjustment_window, T::DampFactor::get()),
-				adjustment_window,
-				T::ClampFactor::get(),
+			// And a block claiming to be from before that counts as 1 millisecond after it
+			finalize_at(MockTime::now() - 3_600_000);
+			let difficulty = DifficultyModule::difficulty().low_u128() as f64;
+			assert!(difficulty > 0.75 * settled && difficulty < 1.25 * settled, "{} from {}", difficulty, settled);
+		});
+	}
+
+	// Timestamps of the past blocks the DAA is given, `None` for those before genesis
+	fn past_timestamps() -> Vec<Option<u64>> {
+		DifficultyModule::past_difficulties_and_timestamps()
+			.into_iter()
+			.map(|past| past.map(|past| past.timestamp))
+			.collect()
+	}
+
+	#[test]
+	fn ring_buffer_keeps_latest_blocks_in_order() {
+		new_test_ext(Daa::DampClamp).execute_with(|| {
+			for _ in 0..10 {
+				finalize_after(3_000);
+			}
+			let mut expected = vec![None; 50];
+			expected.extend((1..=10).map(|block| Some(block * 3_000)));
+			assert_eq!(past_timestamps(), expected);
+
+			for _ in 0..60 {
+				finalize_after(3_000);
+			}
+			let expected: Vec<_> = (11..=70).map(|block| Some(block * 3_000)).collect();
+			assert_eq!(past_timestamps(), expected);
+		});
+	}
+
+	#[test]
+	fn migrate_legacy_window() {
+		reset_mocks(Daa::DampClamp);
+		sp_io::TestExternalities::default().execute_with(|| {
+			let mut legacy = [None; LEGACY_WINDOW_SIZE];
+			for (block, past) in legacy.iter_mut().skip(50).enumerate() {
+				*past = Some(DifficultyAndTimestamp { difficulty: U256::from(block), timestamp: block as u64 });
+			}
+			frame_support::storage::migration::put_storage_value(
+				b"Difficulty",
+				b"PastDifficultiesAndTimestamps",
+				&[],
+				legacy,
 			);
 
-			// minimum difficulty avoids getting stuck due to dampening
-			let difficulty = min(U256::from(T::MaxDifficulty::get()),
-								 max(U256::from(T::MinDifficulty::get()),
-									 diff_sum * U256::from(T::TargetBlockTime::get()) / U256::from(adj_ts)));
+			DifficultyModule::on_runtime_upgrade();
 
-			<PastDifficultiesAndTimestamps<T>>::put(data);
-			<CurrentDifficulty>::put(difficulty);
-		}
+			assert_eq!(StorageVersion::get(), Releases::V2_0_0);
+			assert_eq!(DifficultyModule::past_difficulties_and_timestamps(), legacy.to_vec());
+
+			// The blocks after the upgrade go after the migrated ones
+			finalize_after(3_000);
+			assert_eq!(past_timestamps()[58..], [Some(9), Some(3_000)]);
+		});
+	}
+
+	#[test]
+	fn resize_window_keeps_latest_blocks() {
+		new_test_ext(Daa::DampClamp).execute_with(|| {
+			for _ in 0..20 {
+				finalize_after(3_000);
+			}
+
+			WINDOW.with(|window| window.set(5));
+			DifficultyModule::on_runtime_upgrade();
+			let expected: Vec<_> = (16..=20).map(|block| Some(block * 3_000)).collect();
+			assert_eq!(past_timestamps(), expected);
+
+			finalize_after(3_000);
+			let expected: Vec<_> = (17..=21).map(|block| Some(block * 3_000)).collect();
+			assert_eq!(past_timestamps(), expected);
+
+			WINDOW.with(|window| window.set(8));
+			DifficultyModule::on_runtime_upgrade();
+			let mut expected = vec![None; 3];
+			expected.extend((17..=21).map(|block| Some(block * 3_000)));
+			assert_eq!(past_timestamps(), expected);
+		});
+	}
+
+	#[test]
+	fn zero_window_size_keeps_one_block() {
+		new_test_ext(Daa::DampClamp).execute_with(|| {
+			WINDOW.with(|window| window.set(0));
+			DifficultyModule::on_runtime_upgrade();
+			finalize_after(3_000);
+			finalize_after(3_000);
+			assert_eq!(past_timestamps(), vec![Some(6_000)]);
+		});
+
+		// Nor is a block finalized before the ring buffer is laid out lost
+		reset_mocks(Daa::DampClamp);
+		sp_io::TestExternalities::default().execute_with(|| {
+			finalize_after(3_000);
+			assert_eq!(past_timestamps(), vec![Some(3_000)]);
+		});
+	}
+
+	#[test]
+	fn network_stats_follow_past_blocks() {
+		new_test_ext(Daa::EpochRetarget).execute_with(|| {
+			assert_eq!(DifficultyModule::average_block_time(), None);
+			assert_eq!(DifficultyModule::network_hashrate(), None);
+
+			finalize_after(2_000);
+			assert_eq!(DifficultyModule::average_block_time(), None);
+			assert_eq!(DifficultyModule::network_hashrate(), None);
+
+			finalize_after(2_000);
+			finalize_after(4_000);
+			assert_eq!(DifficultyModule::average_block_time(), Some(3_000));
+			// Two blocks of the initial difficulty in 6 seconds
+			assert_eq!(DifficultyModule::network_hashrate(), Some(U256::from(2 * INITIAL_DIFFICULTY / 6)));
+		});
 	}
 }
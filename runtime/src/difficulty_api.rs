@@ -0,0 +1,19 @@
+use sp_core::U256;
+use sp_std::vec::Vec;
+
+use crate::difficulty::DifficultyAndTimestamp;
+
+sp_api::decl_runtime_apis! {
+	/// Read-only access to the difficulty pallet for monitoring
+	pub trait DifficultyHistoryApi {
+		/// Difficulties and timestamps of the past blocks the DAA is given, from earliest to
+		/// latest. Blocks from before genesis are `None`.
+		fn past_difficulties_and_timestamps() -> Vec<Option<DifficultyAndTimestamp<u64>>>;
+
+		/// Average milliseconds between the past blocks, if there are two or more
+		fn average_block_time() -> Option<u64>;
+
+		/// Hashes per second the network is estimated to make, from the past blocks
+		fn network_hashrate() -> Option<U256>;
+	}
+}
@@ -79,6 +79,19 @@ pub type DigestItem = generic::DigestItem<Hash>;
 /// The UTXO pallet in `./utxo.rs`
 pub mod utxo;
 
+// The code `decl_runtime_apis!` generates for the runtime APIs trips these lints, and an allow
+// on the macro call does not reach it
+/// The UtxoApi runtime API in `./utxo_api.rs`
+#[allow(clippy::too_many_arguments, clippy::unnecessary_mut_passed)]
+pub mod utxo_api;
+
+/// The DifficultyHistoryApi runtime API in `./difficulty_api.rs`
+#[allow(clippy::too_many_arguments, clippy::unnecessary_mut_passed)]
+pub mod difficulty_api;
+
+/// The locking Script checker in `./script.rs`
+pub mod script;
+
 /// The BlockAuthor trait in `./block_author.rs`
 pub mod block_author;
 
@@ -110,10 +123,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("utxo"),
 	impl_name: create_runtime_str!("utxo"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 5,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 2,
 };
 
 /// The version information used to identify this runtime when compiled natively.
@@ -232,6 +245,7 @@ parameter_types! {
 	pub const DampFactor: u128 = 3;
 	pub const ClampFactor: u128 = 2;
 	pub const MaxDifficulty: u128 = u128::max_value();
+	pub const DifficultyWindow: u32 = 60;
 }
 
 impl difficulty::Trait for Runtime {
@@ -242,14 +256,26 @@ impl difficulty::Trait for Runtime {
 	type MaxDifficulty = MaxDifficulty;
 	// Setting min difficulty to damp factor per recommendation
 	type MinDifficulty = DampFactor;
+	// Other DAAs to experiment with are `EpochRetarget`, `Asert` and `Lwma`
+	type Algorithm = difficulty::DampClamp;
+	type WindowSize = DifficultyWindow;
 }
 
 impl block_author::Trait for Runtime {}
 
+parameter_types! {
+	pub const CoinbaseMaturity: BlockNumber = 100;
+	pub const IndexOwners: bool = true;
+}
+
 impl utxo::Trait for Runtime {
 	type Event = Event;
 	type BlockAuthor = BlockAuthor;
 	type Issuance = issuance::BitcoinHalving;
+	type TimeProvider = Timestamp;
+	type CoinbaseMaturity = CoinbaseMaturity;
+	type IndexOwners = IndexOwners;
+	type WeightInfo = ();
 }
 
 construct_runtime!(
@@ -351,10 +377,11 @@ impl_runtime_apis! {
 			// Extrinsics representing UTXO transaction need some special handling
 			if let Some(&utxo::Call::spend(ref transaction)) = IsSubType::<<Utxo as Callable<Runtime>>::Call>::is_sub_type(&tx.function) {
 				match Utxo::validate_transaction(&transaction) {
-					// Transaction verification failed
+					// Transaction verification failed, report which check in the custom code
 					Err(e) => {
-						sp_runtime::print(e);
-						return Err(TransactionValidityError::Invalid(InvalidTransaction::Custom(1)));
+						let code = e.code();
+						sp_runtime::print(<&'static str>::from(e));
+						return Err(TransactionValidityError::Invalid(InvalidTransaction::Custom(code)));
 					}
 					// Race condition, or Transaction is good to go
 					Ok(tv) => { return Ok(tv); }
@@ -384,9 +411,160 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl utxo_api::UtxoApi<Block> for Runtime {
+		fn get_utxo(outpoint: utxo::OutPoint) -> Option<utxo::TransactionOutput> {
+			Utxo::utxo(outpoint)
+		}
+
+		fn list_unspent(
+			owner: Hash,
+			after: Option<utxo::OutPoint>,
+			count: u32,
+		) -> Vec<(utxo::OutPoint, utxo::TransactionOutput)> {
+			Utxo::unspent_by_owner(&owner, after, count)
+		}
+
+		fn validate(
+			transaction: utxo::Transaction,
+		) -> Result<sp_runtime::transaction_validity::ValidTransaction, sp_runtime::DispatchError> {
+			Utxo::validate_transaction(&transaction).map_err(Into::into)
+		}
+
+		fn simple_transaction_bytes(transaction: utxo::Transaction) -> Vec<u8> {
+			Utxo::get_simple_transaction(&transaction)
+		}
+
+		fn fee(transaction: utxo::Transaction) -> Option<utxo::Value> {
+			Utxo::fee(&transaction)
+		}
+
+		fn spend_weight(transaction: utxo::Transaction) -> Weight {
+			Utxo::spend_weight(&transaction)
+		}
+
+		fn total_issuance() -> utxo::Value {
+			Utxo::total_issuance()
+		}
+
+		fn lock_clock() -> (u64, u64) {
+			Utxo::pool_clock()
+		}
+	}
+
 	impl sp_consensus_pow::DifficultyApi<Block, U256> for Runtime {
 		fn difficulty() -> U256 {
 			DifficultyAdjustment::difficulty()
 		}
 	}
+
+	impl difficulty_api::DifficultyHistoryApi<Block> for Runtime {
+		fn past_difficulties_and_timestamps() -> Vec<Option<difficulty::DifficultyAndTimestamp<u64>>> {
+			DifficultyAdjustment::past_difficulties_and_timestamps()
+		}
+
+		fn average_block_time() -> Option<u64> {
+			DifficultyAdjustment::average_block_time()
+		}
+
+		fn network_hashrate() -> Option<U256> {
+			DifficultyAdjustment::network_hashrate()
+		}
+	}
+
+	#[cfg(feature = "runtime-benchmarks")]
+	impl frame_benchmarking::Benchmark<Block> for Runtime {
+		fn dispatch_benchmark(
+			pallet: Vec<u8>,
+			benchmark: Vec<u8>,
+			lowest_range_values: Vec<u32>,
+			highest_range_values: Vec<u32>,
+			steps: Vec<u32>,
+			repeat: u32,
+			extra: bool,
+		) -> Result<Vec<frame_benchmarking::BenchmarkBatch>, sp_runtime::RuntimeString> {
+			use frame_benchmarking::{Benchmarking, BenchmarkBatch, TrackedStorageKey, add_benchmark};
+			use sp_io::hashing::twox_128;
+
+			// System storage touched by every block, which should not count towards the weights
+			let system_key = |item: &[u8]| -> TrackedStorageKey {
+				[twox_128(b"System"), twox_128(item)].concat().into()
+			};
+			let whitelist: Vec<TrackedStorageKey> = vec![
+				system_key(b"Number"),
+				system_key(b"ExecutionPhase"),
+				system_key(b"EventCount"),
+				system_key(b"Events"),
+			];
+
+			let mut batches = Vec::<BenchmarkBatch>::new();
+			let params = (&pallet, &benchmark, &lowest_range_values, &highest_range_values, &steps, repeat, &whitelist, extra);
+
+			add_benchmark!(params, batches, utxo, Utxo);
+
+			if batches.is_empty() { return Err("Benchmark not found for this pallet.".into()) }
+			Ok(batches)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use frame_support::StorageValue;
+	use frame_system::{LastRuntimeUpgrade, LastRuntimeUpgradeInfo};
+	use sp_core::H256;
+	use sp_runtime::traits::Header as _;
+	use script::Script;
+	use utxo::{OutPoint, TransactionOutput};
+
+	/// Storage of a chain started on the first release, upgraded by importing a block
+	#[test]
+	fn upgrade_migrates_first_release_storage() {
+		sp_io::TestExternalities::default().execute_with(|| {
+			LastRuntimeUpgrade::put(LastRuntimeUpgradeInfo {
+				spec_version: 1.into(),
+				spec_name: "utxo".into(),
+			});
+			// A legacy output encodes as its value followed by the owner's public key
+			let legacy_utxo = H256::repeat_byte(1);
+			let pubkey = H256::repeat_byte(2);
+			frame_support::storage::migration::put_storage_value(
+				b"Utxo",
+				b"UtxoStore",
+				legacy_utxo.as_bytes(),
+				(100 as utxo::Value, pubkey),
+			);
+			let mut legacy_window = [None; difficulty::LEGACY_WINDOW_SIZE];
+			legacy_window[difficulty::LEGACY_WINDOW_SIZE - 1] = Some(difficulty::DifficultyAndTimestamp {
+				difficulty: U256::from(1_000),
+				timestamp: 7,
+			});
+			frame_support::storage::migration::put_storage_value(
+				b"Difficulty",
+				b"PastDifficultiesAndTimestamps",
+				&[],
+				legacy_window,
+			);
+
+			Executive::initialize_block(&Header::new(
+				1,
+				Default::default(),
+				Default::default(),
+				Default::default(),
+				Default::default(),
+			));
+
+			let migrated = (
+				OutPoint { txid: legacy_utxo, index: 0 },
+				TransactionOutput { value: 100, script: Script::PayToPubkey(pubkey) },
+			);
+			assert_eq!(Utxo::utxo(migrated.0), Some(migrated.1.clone()));
+			assert_eq!(Utxo::total_issuance(), 100);
+			assert_eq!(Utxo::unspent_by_owner(&pubkey, None, 10), vec![migrated]);
+			assert_eq!(
+				DifficultyAdjustment::past_difficulties_and_timestamps().last(),
+				Some(&legacy_window[difficulty::LEGACY_WINDOW_SIZE - 1])
+			);
+		});
+	}
 }
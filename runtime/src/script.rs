@@ -0,0 +1,264 @@
+//! Locking scripts that guard UTXOs, and the checker that evaluates them.
+//!
+//! Every `TransactionOutput` carries a `Script` describing the conditions under which it may be
+//! spent. A spending `TransactionInput` supplies a `SigScript` holding the signatures and any
+//! other data the script asks for. The checker never mutates storage, so it can be called from
+//! both the transaction pool and block execution.
+
+use codec::{Decode, Encode};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::{
+	H256,
+	H512,
+	sr25519::{Public, Signature},
+};
+use sp_runtime::traits::{BlakeTwo256, Hash};
+use sp_std::{collections::btree_set::BTreeSet, vec::Vec};
+
+use crate::utxo::LockTime;
+
+/// Upper bound on the number of keys in a `MultiSig` script, which bounds
+/// the signature checks a single input can cause
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+/// Spending condition attached to a transaction output
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Encode, Decode, Hash, Debug)]
+pub enum Script {
+	/// Spendable with a signature from the given sr25519 public key
+	PayToPubkey(H256),
+
+	/// Spendable by revealing a public key whose BlakeTwo256 hash matches,
+	/// and signing with the corresponding private key
+	PayToPubkeyHash(H256),
+
+	/// Spendable with valid signatures from at least `threshold` of `pubkeys`.
+	/// The keys must be unique, and `threshold` between 1 and the number of keys.
+	MultiSig {
+		threshold: u32,
+		pubkeys: Vec<H256>,
+	},
+
+	/// Spendable by revealing the BlakeTwo256 preimage of `hash`,
+	/// and signing with the private key of `pubkey`
+	HashLock {
+		hash: H256,
+		pubkey: H256,
+	},
+
+	/// Spendable with a signature from `pubkey` once the chain has reached block `height`
+	TimeLock {
+		height: u64,
+		pubkey: H256,
+	},
+
+	/// Hash time locked contract, as used for cross-chain atomic swaps.
+	/// Spendable by revealing a nonempty preimage of `hash` and signing with the private key
+	/// of `recipient`, or by signing with the private key of `refund` with an empty redeemer
+	/// once the chain has reached block `timeout`.
+	Htlc {
+		hash_type: HashType,
+		hash: H256,
+		recipient: H256,
+		refund: H256,
+		timeout: u64,
+	},
+}
+
+/// Hash function a hash locked script commits with
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Encode, Decode, Hash, Debug)]
+pub enum HashType {
+	BlakeTwo256,
+	Sha256,
+}
+
+impl HashType {
+	/// Hash `data` with this hash function
+	pub fn hash(&self, data: &[u8]) -> H256 {
+		match self {
+			HashType::BlakeTwo256 => BlakeTwo256::hash(data),
+			HashType::Sha256 => H256::from(sp_io::hashing::sha2_256(data)),
+		}
+	}
+}
+
+impl Default for Script {
+	fn default() -> Self {
+		Script::PayToPubkey(H256::zero())
+	}
+}
+
+/// Data supplied by a transaction input to satisfy the script of the UTXO it spends
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
+pub struct SigScript {
+	/// Signatures over the simple transaction. Single key scripts use the first one only.
+	/// Multisig scripts expect them in the same order as the script's public keys.
+	pub signatures: Vec<H512>,
+
+	/// Additional data revealed to the script: the public key for `PayToPubkeyHash`,
+	/// or the preimage for `HashLock`
+	pub redeemer: Vec<u8>,
+}
+
+impl From<H512> for SigScript {
+	fn from(signature: H512) -> Self {
+		SigScript {
+			signatures: sp_std::vec![signature],
+			redeemer: Vec::new(),
+		}
+	}
+}
+
+/// Reasons a script is malformed or a sigscript fails to satisfy it
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ScriptError {
+	/// A multisig script has more than `MAX_MULTISIG_KEYS` keys
+	TooManyMultisigKeys,
+	/// A multisig threshold is zero or exceeds the number of keys
+	InvalidMultisigThreshold,
+	/// A multisig script lists the same key more than once
+	DuplicateMultisigKey,
+	/// The sigscript carries no signature
+	MissingSignature,
+	/// A signature does not verify against the expected key
+	BadSignature,
+	/// The redeemer of a pay to pubkey hash spend is not a public key
+	RedeemerNotPublicKey,
+	/// The revealed public key does not match the script's hash
+	PublicKeyHashMismatch,
+	/// Fewer signatures than the multisig threshold
+	NotEnoughSignatures,
+	/// More signatures than multisig keys
+	TooManySignatures,
+	/// The revealed preimage does not match the script's hash
+	PreimageMismatch,
+}
+
+/// Verify a single sr25519 signature over `message`
+fn verify(signature: &H512, message: &[u8], pubkey: &H256) -> bool {
+	sp_io::crypto::sr25519_verify(
+		&Signature::from_raw(*signature.as_fixed_bytes()),
+		message,
+		&Public::from_raw(*pubkey.as_fixed_bytes()),
+	)
+}
+
+/// Check that the first signature of `sigscript` was made by `pubkey`
+fn check_signature(sigscript: &SigScript, message: &[u8], pubkey: &H256) -> Result<(), ScriptError> {
+	let signature = sigscript.signatures.first().ok_or(ScriptError::MissingSignature)?;
+	if verify(signature, message, pubkey) {
+		Ok(())
+	} else {
+		Err(ScriptError::BadSignature)
+	}
+}
+
+/// Check that `script` is well formed before it is used to lock a new output
+pub fn validate(script: &Script) -> Result<(), ScriptError> {
+	if let Script::MultiSig { threshold, pubkeys } = script {
+		if pubkeys.len() > MAX_MULTISIG_KEYS {
+			return Err(ScriptError::TooManyMultisigKeys);
+		}
+		if *threshold == 0 || *threshold as usize > pubkeys.len() {
+			return Err(ScriptError::InvalidMultisigThreshold);
+		}
+		let key_set: BTreeSet<_> = pubkeys.iter().collect();
+		if key_set.len() != pubkeys.len() {
+			return Err(ScriptError::DuplicateMultisigKey);
+		}
+	}
+	Ok(())
+}
+
+/// Evaluate `script` against the data supplied in `sigscript`
+///
+/// `message` is the payload every signature must commit to, usually the simple transaction.
+/// Returns the absolute lock the spend has to wait for, which is the height of a `TimeLock`
+/// or the timeout of an `Htlc` refund. Like any other lock, the caller enforces it.
+pub fn check(script: &Script, sigscript: &SigScript, message: &[u8]) -> Result<LockTime, ScriptError> {
+	check_sigscript(script, sigscript, message)?;
+	Ok(match script {
+		Script::TimeLock { height, .. } => LockTime::Block(*height),
+		Script::Htlc { timeout, .. } if sigscript.redeemer.is_empty() => LockTime::Block(*timeout),
+		_ => LockTime::None,
+	})
+}
+
+/// Check the signatures and other data `sigscript` supplies to spend `script`
+fn check_sigscript(script: &Script, sigscript: &SigScript, message: &[u8]) -> Result<(), ScriptError> {
+	match script {
+		Script::PayToPubkey(pubkey) => check_signature(sigscript, message, pubkey),
+		Script::PayToPubkeyHash(pubkey_hash) => {
+			if sigscript.redeemer.len() != 32 {
+				return Err(ScriptError::RedeemerNotPublicKey);
+			}
+			let pubkey = H256::from_slice(&sigscript.redeemer);
+			if BlakeTwo256::hash(pubkey.as_bytes()) != *pubkey_hash {
+				return Err(ScriptError::PublicKeyHashMismatch);
+			}
+			check_signature(sigscript, message, &pubkey)
+		},
+		Script::MultiSig { threshold, pubkeys } => {
+			if sigscript.signatures.len() < *threshold as usize {
+				return Err(ScriptError::NotEnoughSignatures);
+			}
+			if sigscript.signatures.len() > pubkeys.len() {
+				return Err(ScriptError::TooManySignatures);
+			}
+			// Signatures must appear in the same order as the keys they belong to,
+			// so each key can be matched at most once and a signer is never counted twice.
+			let mut keys = pubkeys.iter();
+			for signature in sigscript.signatures.iter() {
+				if !keys.any(|pubkey| verify(signature, message, pubkey)) {
+					return Err(ScriptError::BadSignature);
+				}
+			}
+			Ok(())
+		},
+		Script::HashLock { hash, pubkey } => {
+			if BlakeTwo256::hash(&sigscript.redeemer) != *hash {
+				return Err(ScriptError::PreimageMismatch);
+			}
+			check_signature(sigscript, message, pubkey)
+		},
+		Script::TimeLock { pubkey, .. } => check_signature(sigscript, message, pubkey),
+		Script::Htlc { hash_type, hash, recipient, refund, .. } => {
+			if sigscript.redeemer.is_empty() {
+				check_signature(sigscript, message, refund)
+			} else {
+				if hash_type.hash(&sigscript.redeemer) != *hash {
+					return Err(ScriptError::PreimageMismatch);
+				}
+				check_signature(sigscript, message, recipient)
+			}
+		},
+	}
+}
+
+/// The hash and preimage revealed by `sigscript` when spending `script`, if any
+///
+/// Only meaningful once `check` has succeeded for the same script and sigscript.
+pub fn revealed_preimage<'a>(script: &Script, sigscript: &'a SigScript) -> Option<(H256, &'a [u8])> {
+	match script {
+		Script::HashLock { hash, .. } => Some((*hash, &sigscript.redeemer)),
+		Script::Htlc { hash, .. } if !sigscript.redeemer.is_empty() => Some((*hash, &sigscript.redeemer)),
+		_ => None,
+	}
+}
+
+/// The keys named by `script`, which is who it is listed under in the owner index.
+/// A `PayToPubkeyHash` script only names the hash of its key.
+pub fn owners(script: &Script) -> Vec<H256> {
+	match script {
+		Script::PayToPubkey(pubkey)
+		| Script::PayToPubkeyHash(pubkey)
+		| Script::HashLock { pubkey, .. }
+		| Script::TimeLock { pubkey, .. } => sp_std::vec![*pubkey],
+		Script::MultiSig { pubkeys, .. } => pubkeys.clone(),
+		Script::Htlc { recipient, refund, .. } if recipient == refund => sp_std::vec![*recipient],
+		Script::Htlc { recipient, refund, .. } => sp_std::vec![*recipient, *refund],
+	}
+}
@@ -1,23 +1,34 @@
 use codec::{Decode, Encode};
 use frame_support::{
-	decl_event, decl_module, decl_storage,
-	dispatch::{DispatchResult, Vec},
+	decl_error, decl_event, decl_module, decl_storage,
+	dispatch::{DispatchResult, DispatchResultWithPostInfo, Vec},
 	ensure,
+	storage::{generator::StorageDoubleMap as StorageDoubleMapGenerator, migration::StorageKeyIterator},
+	traits::{Get, Time},
+	weights::Weight,
+	Identity,
 };
 #[cfg(feature = "std")]
 use serde::{Deserialize, Serialize};
 use sp_core::{
 	crypto::Public as _,
 	H256,
-	H512,
-	sr25519::{Public, Signature},
+	sr25519::Public,
 };
 use sp_std::collections::btree_map::BTreeMap;
 use sp_runtime::{
 	traits::{BlakeTwo256, Hash, SaturatedConversion},
 	transaction_validity::{TransactionLongevity, ValidTransaction},
 };
-use super::{block_author::BlockAuthor, issuance::Issuance};
+use super::{block_author::BlockAuthor, issuance::Issuance, script::{self, Script, ScriptError, SigScript}};
+
+mod benchmarking;
+mod default_weight;
+
+pub trait WeightInfo {
+	fn spend(i: u32, o: u32) -> Weight;
+	fn check_multisig(k: u32) -> Weight;
+}
 
 pub trait Trait: frame_system::Trait {
 	/// The ubiquitous Event type
@@ -28,10 +39,25 @@ pub trait Trait: frame_system::Trait {
 
 	/// A source to determine the issuance portion of the block reward
 	type Issuance: Issuance<<Self as frame_system::Trait>::BlockNumber, Value>;
+
+	/// A source for timestamp data, used to evaluate time based locks
+	type TimeProvider: Time;
+
+	/// Number of blocks a block reward UTXO must wait before it can be spent,
+	/// so that rewards of blocks lost to a reorg cannot already have moved on
+	type CoinbaseMaturity: Get<<Self as frame_system::Trait>::BlockNumber>;
+
+	/// Whether to maintain `UtxosByOwner`, the index of UTXOs by the keys named in their scripts
+	type IndexOwners: Get<bool>;
+
+	/// Weight information for the extrinsics in this pallet.
+	type WeightInfo: WeightInfo;
 }
 
 pub type Value = u128;
 
+type MomentOf<T> = <<T as Trait>::TimeProvider as Time>::Moment;
+
 /// Single transaction to be dispatched
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
 #[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
@@ -41,6 +67,22 @@ pub struct Transaction {
 
 	/// UTXOs to be created as a result of current transaction dispatch
 	pub outputs: Vec<TransactionOutput>,
+
+	/// Absolute lock: the transaction cannot be dispatched before this
+	/// block number or time
+	pub lock_time: LockTime,
+}
+
+/// Reference to a single output of a transaction, the key of an UTXO
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Copy, Encode, Decode, Hash, Debug)]
+pub struct OutPoint {
+	/// Id of the transaction that created the output. This is the BlakeTwo256 hash
+	/// of the simple transaction, so it does not depend on signatures.
+	pub txid: H256,
+
+	/// Position of the output in that transaction's outputs
+	pub index: u32,
 }
 
 /// Single transaction input that refers to one UTXO
@@ -48,11 +90,16 @@ pub struct Transaction {
 #[derive(PartialEq, Eq, PartialOrd, Ord, Default, Clone, Encode, Decode, Hash, Debug)]
 pub struct TransactionInput {
 	/// Reference to an UTXO to be spent
-	pub outpoint: H256,
+	pub outpoint: OutPoint,
 
 	/// Proof that transaction owner is authorized to spend referred UTXO &
-	/// that the entire transaction is untampered
-	pub sigscript: H512,
+	/// that the entire transaction is untampered. Must satisfy the `Script`
+	/// of the referred UTXO.
+	pub sigscript: SigScript,
+
+	/// Relative lock: the referred UTXO cannot be spent until it has existed
+	/// for this number of blocks or milliseconds
+	pub relative_lock: LockTime,
 }
 
 /// Single transaction output to create upon transaction dispatch
@@ -62,32 +109,168 @@ pub struct TransactionOutput {
 	/// Value associated with this output
 	pub value: Value,
 
-	/// Public key associated with this output. In order to spend this output
-	/// owner must provide a proof by hashing the whole `Transaction` and
-	/// signing it with a corresponding private key.
-	pub pubkey: H256,
+	/// Locking script associated with this output. In order to spend this output
+	/// owner must provide a `SigScript` that satisfies it, usually by signing
+	/// the simple transaction with a corresponding private key.
+	pub script: Script,
+}
+
+/// A lock on spending, measured either in blocks or in milliseconds of the `TimeProvider`.
+/// Used as an absolute block number or unix time by `Transaction::lock_time`, and as a
+/// number of blocks or milliseconds since the referred UTXO was created by
+/// `TransactionInput::relative_lock`.
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Encode, Decode, Hash, Debug)]
+pub enum LockTime {
+	/// Not locked
+	None,
+	/// Locked until a block number, or for a number of blocks
+	Block(u64),
+	/// Locked until a unix time in milliseconds, or for a number of milliseconds
+	Time(u64),
+}
+
+impl Default for LockTime {
+	fn default() -> Self {
+		LockTime::None
+	}
+}
+
+impl LockTime {
+	/// Turn a lock relative to the given block number and time into an absolute one
+	pub fn after(self, block: u64, time: u64) -> Self {
+		match self {
+			LockTime::None => LockTime::None,
+			LockTime::Block(blocks) => LockTime::Block(block.saturating_add(blocks)),
+			LockTime::Time(millis) => LockTime::Time(time.saturating_add(millis)),
+		}
+	}
+
+	/// Whether an absolute lock has expired at the given block number and time
+	pub fn has_expired(&self, block: u64, time: u64) -> bool {
+		match *self {
+			LockTime::None => true,
+			LockTime::Block(until) => block >= until,
+			LockTime::Time(until) => time >= until,
+		}
+	}
+
+	/// Tag a transaction waiting in the pool for this absolute lock requires. Time locks are
+	/// rounded up to `TIME_LOCK_TAG_MILLIS`, so that the tags which expire between two blocks
+	/// can be listed.
+	pub fn pool_tag(&self) -> Vec<u8> {
+		let lock = match *self {
+			LockTime::Time(until) => LockTime::Time(
+				until.saturating_add(TIME_LOCK_TAG_MILLIS - 1) / TIME_LOCK_TAG_MILLIS * TIME_LOCK_TAG_MILLIS
+			),
+			lock => lock,
+		};
+		(b"lock_time", lock).encode()
+	}
+
+	/// Pool tags of the locks that expire as the pool clock, see `Module::pool_clock`,
+	/// moves from the block number and time `from` to `to`
+	pub fn expired_pool_tags(from: (u64, u64), to: (u64, u64)) -> Vec<Vec<u8>> {
+		let blocks = (from.0.saturating_add(1)..=to.0).map(LockTime::Block);
+		let times = (from.1 / TIME_LOCK_TAG_MILLIS + 1..=to.1 / TIME_LOCK_TAG_MILLIS)
+			.map(|period| LockTime::Time(period * TIME_LOCK_TAG_MILLIS));
+		blocks.chain(times).map(|lock| lock.pool_tag()).collect()
+	}
+}
+
+/// Granularity of the pool tags of time locks, in milliseconds
+pub const TIME_LOCK_TAG_MILLIS: u64 = 1_000;
+
+/// The block and time at which an UTXO was created
+#[derive(PartialEq, Eq, Clone, Copy, Default, Encode, Decode, Debug)]
+pub struct UtxoOrigin<BlockNumber, Moment> {
+	pub block: BlockNumber,
+	pub timestamp: Moment,
+	/// Whether the UTXO is a block reward, subject to `CoinbaseMaturity`
+	pub coinbase: bool,
+}
+
+// A value placed in storage that represents the current version of the Utxo storage.
+// This value is used by the `on_runtime_upgrade` logic to determine whether we run
+// storage migration logic.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Releases {
+	/// `UtxoStore` keyed by opaque hashes, mostly of the full signed transaction and
+	/// output index, holding outputs locked to a bare public key
+	V1_0_0,
+	/// `UtxoStore` keyed by `OutPoint`, holding outputs locked by a `Script`
+	V2_0_0,
+	/// `TotalIssuance` tracks all value ever issued
+	V3_0_0,
+	/// `UtxosByOwner` indexes UTXOs by the keys named in their scripts
+	V4_0_0,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V1_0_0
+	}
+}
+
+/// Transaction output as stored before `Releases::V2_0_0`
+#[derive(Encode, Decode)]
+struct LegacyTransactionOutput {
+	value: Value,
+	pubkey: H256,
 }
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Utxo {
 		/// All valid unspent transaction outputs are stored in this map.
-		/// Initial set of UTXO is populated from the list stored in genesis.
-		/// We use the identity hasher here because the cryptographic hashing is
-		/// done explicitly. TODO In the future we should remove the explicit hashing,
-		/// and use blake2_128_concat here. I'm deferring that so as not to break
-		/// the workshop inputs.
-		UtxoStore build(|config: &GenesisConfig| {
-			config.genesis_utxos
-				.iter()
-				.cloned()
-				.map(|u| (BlakeTwo256::hash_of(&u), u))
+		/// Initial set of UTXO is populated from the list stored in genesis, as the
+		/// outputs of a single genesis transaction without inputs.
+		/// We use the identity hasher here because the txid in the key already is a
+		/// cryptographic hash. TODO In the future we should use blake2_128_concat here.
+		/// I'm deferring that so as not to break the workshop inputs.
+		pub UtxoStore get(fn utxo) build(|config: &GenesisConfig| {
+			genesis_outpoints(&config.genesis_utxos)
+		}): map hasher(identity) OutPoint => Option<TransactionOutput>;
+
+		/// Outpoints of the UTXOs in `UtxoStore`, keyed by each key named in their script.
+		/// Only maintained if `Trait::IndexOwners` is set.
+		pub UtxosByOwner build(|config: &GenesisConfig| {
+			if !T::IndexOwners::get() {
+				return Vec::new();
+			}
+			genesis_outpoints(&config.genesis_utxos)
+				.into_iter()
+				.flat_map(|(outpoint, utxo)| script::owners(&utxo.script)
+					.into_iter()
+					.map(move |owner| (owner, outpoint, ())))
 				.collect::<Vec<_>>()
-		}): map hasher(identity) H256 => Option<TransactionOutput>;
+		}): double_map hasher(identity) H256, hasher(identity) OutPoint => ();
+
+		/// Block and time each UTXO in `UtxoStore` was created, used to evaluate
+		/// relative locks. Genesis UTXOs have no entry and count as created at block zero.
+		pub UtxoOrigins get(fn utxo_origin):
+			map hasher(identity) OutPoint => Option<UtxoOrigin<T::BlockNumber, MomentOf<T>>>;
+
+		/// Number of the latest block. `frame_system` clears its own once the block is
+		/// finalized, but the transaction pool judges locks against the block after it.
+		pub LastBlockNumber get(fn last_block_number): T::BlockNumber;
 
 		/// Total reward value to be redistributed among authorities.
 		/// It is accumulated from transactions during block execution
 		/// and then dispersed to validators on block finalization.
 		pub RewardTotal get(fn reward_total): Value;
+
+		/// Total value issued at genesis and by block rewards, held in UTXOs
+		/// or in `RewardTotal` waiting to be dispersed.
+		pub TotalIssuance get(fn total_issuance) build(|config: &GenesisConfig| {
+			config.genesis_utxos
+				.iter()
+				.fold(0, |total: Value, utxo| total.saturating_add(utxo.value))
+		}): Value;
+
+		/// Storage version of the pallet.
+		///
+		/// This is set to v4.0.0 for new networks.
+		StorageVersion build(|_: &GenesisConfig| Releases::V4_0_0): Releases;
 	}
 
 	add_extra_genesis {
@@ -95,27 +278,146 @@ decl_storage! {
 	}
 }
 
+/// Key the genesis UTXOs as the outputs of a single transaction without inputs
+fn genesis_outpoints(genesis_utxos: &[TransactionOutput]) -> Vec<(OutPoint, TransactionOutput)> {
+	let genesis_transaction = Transaction {
+		outputs: genesis_utxos.to_vec(),
+		..Default::default()
+	};
+	// Without inputs there are no signatures to strip from the txid
+	let txid = BlakeTwo256::hash_of(&genesis_transaction);
+	genesis_transaction.outputs
+		.into_iter()
+		.enumerate()
+		.map(|(index, u)| (OutPoint { txid, index: index as u32 }, u))
+		.collect()
+}
+
+decl_error! {
+	/// Reasons a UTXO transaction is rejected. The transaction pool reports them as
+	/// `InvalidTransaction::Custom` with the code given by `Error::code`.
+	pub enum Error for Module<T: Trait> {
+		/// Transaction has no inputs
+		NoInputs,
+		/// Transaction has no outputs
+		NoOutputs,
+		/// An input is used more than once
+		DuplicateInput,
+		/// An output is defined more than once
+		DuplicateOutput,
+		/// An output has zero value
+		ZeroOutputValue,
+		/// An output would overwrite an existing UTXO
+		OutputAlreadyExists,
+		/// Total output value exceeds total input value
+		OutputExceedsInput,
+		/// A value or output index overflowed
+		Overflow,
+		/// An input refers to an UTXO that does not exist (yet)
+		MissingInputs,
+		/// A lock time or coinbase maturity has not expired yet
+		TransactionLocked,
+		/// A multisig output script has too many keys
+		TooManyMultisigKeys,
+		/// A multisig output script threshold is zero or exceeds the number of keys
+		InvalidMultisigThreshold,
+		/// A multisig output script lists the same key more than once
+		DuplicateMultisigKey,
+		/// An input carries no signature
+		MissingSignature,
+		/// An input signature does not verify
+		BadSignature,
+		/// A pay to pubkey hash input reveals something other than a public key
+		RedeemerNotPublicKey,
+		/// A pay to pubkey hash input reveals the wrong public key
+		PublicKeyHashMismatch,
+		/// A multisig input has fewer signatures than the threshold
+		NotEnoughSignatures,
+		/// A multisig input has more signatures than keys
+		TooManySignatures,
+		/// A hash locked input reveals the wrong preimage
+		PreimageMismatch,
+	}
+}
+
+impl<T: Trait> Error<T> {
+	/// Code of the error in `InvalidTransaction::Custom`, which is its index in metadata
+	pub fn code(&self) -> u8 {
+		self.as_u8()
+	}
+}
+
+impl<T: Trait> From<ScriptError> for Error<T> {
+	fn from(error: ScriptError) -> Self {
+		match error {
+			ScriptError::TooManyMultisigKeys => Error::TooManyMultisigKeys,
+			ScriptError::InvalidMultisigThreshold => Error::InvalidMultisigThreshold,
+			ScriptError::DuplicateMultisigKey => Error::DuplicateMultisigKey,
+			ScriptError::MissingSignature => Error::MissingSignature,
+			ScriptError::BadSignature => Error::BadSignature,
+			ScriptError::RedeemerNotPublicKey => Error::RedeemerNotPublicKey,
+			ScriptError::PublicKeyHashMismatch => Error::PublicKeyHashMismatch,
+			ScriptError::NotEnoughSignatures => Error::NotEnoughSignatures,
+			ScriptError::TooManySignatures => Error::TooManySignatures,
+			ScriptError::PreimageMismatch => Error::PreimageMismatch,
+		}
+	}
+}
+
 // External functions: callable by the end user
 decl_module! {
 	pub struct Module<T: Trait> for enum Call where origin: T::Origin {
+		type Error = Error<T>;
+
 		fn deposit_event() = default;
 
+		/// Number of blocks a block reward UTXO must wait before it can be spent
+		const CoinbaseMaturity: T::BlockNumber = T::CoinbaseMaturity::get();
+
 		/// Dispatch a single transaction and update UTXO set accordingly
-		#[weight = 1_000_000] //TODO weight should be proportional to number of inputs + outputs
-		pub fn spend(_origin, transaction: Transaction) -> DispatchResult {
+		#[weight = Module::<T>::spend_weight(transaction)]
+		pub fn spend(_origin, transaction: Transaction) -> DispatchResultWithPostInfo {
 									// TransactionValidity{}
-			let transaction_validity = Self::validate_transaction(&transaction)?;
-			ensure!(transaction_validity.requires.is_empty(), "missing inputs");
+			let transaction_validity = Self::validate_transaction_at(
+				&transaction,
+				<frame_system::Module<T>>::block_number().saturated_into::<u64>(),
+				T::TimeProvider::now().saturated_into::<u64>(),
+			)?;
+			ensure!(Self::get_missing_utxos(&transaction).is_empty(), Error::<T>::MissingInputs);
+			ensure!(transaction_validity.requires.is_empty(), Error::<T>::TransactionLocked);
+
+			// Collect revealed preimages and multisig keys before the spent UTXOs are removed
+			let revealed_preimages = Self::get_revealed_preimages(&transaction);
+			let actual_weight = Self::spend_weight_with_keys(&transaction, Self::extra_multisig_keys(&transaction));
 
 			Self::update_storage(&transaction, transaction_validity.priority as Value)?;
 
-			Self::deposit_event(Event::TransactionSuccess(transaction));
+			for (hash, preimage) in revealed_preimages {
+				Self::deposit_event(Event::PreimageRevealed(hash, preimage));
+			}
+			Self::deposit_event(Event::TransactionSuccess(Self::get_txid(&transaction), transaction));
+
+			Ok(Some(actual_weight).into())
+		}
 
-			Ok(())
+		/// Bring storage of chains started on earlier releases to the current layout
+		fn on_runtime_upgrade() -> Weight {
+			let mut weight = 0;
+			if StorageVersion::get() == Releases::V1_0_0 {
+				weight += Self::migrate_to_outpoints();
+			}
+			if StorageVersion::get() == Releases::V2_0_0 {
+				weight += Self::migrate_total_issuance();
+			}
+			if StorageVersion::get() == Releases::V3_0_0 {
+				weight += Self::migrate_owner_index();
+			}
+			weight
 		}
 
 		/// Handler called by the system on block finalization
-		fn on_finalize() {
+		fn on_finalize(block_number: T::BlockNumber) {
+			<LastBlockNumber<T>>::put(block_number);
 			match T::BlockAuthor::block_author() {
 				// Block author did not provide key to claim reward
 				None => Self::deposit_event(Event::RewardsWasted),
@@ -128,88 +430,147 @@ decl_module! {
 
 decl_event!(
 	pub enum Event {
-		/// Transaction was executed successfully
-		TransactionSuccess(Transaction),
-		/// Rewards were issued. Amount, UTXO hash.
-		RewardsIssued(Value, H256),
+		/// Transaction was executed successfully. Transaction id, transaction.
+		TransactionSuccess(H256, Transaction),
+		/// Rewards were issued. Amount, UTXO outpoint.
+		RewardsIssued(Value, OutPoint),
 		/// Rewards were wasted
 		RewardsWasted,
+		/// A hash locked UTXO was spent by revealing its preimage. Hash, preimage.
+		/// Lets watchers complete the counter-leg of an atomic swap.
+		PreimageRevealed(H256, Vec<u8>),
 	}
 );
 
 // "Internal" functions, callable by code.
 impl<T: Trait> Module<T> {
 
-	/// Check transaction for validity, errors, & race conditions
+	/// Check transaction for validity as the transaction pool does, for inclusion in the block
+	/// after the latest one. Pending locks are left for the pool as tags the transaction requires.
+	pub fn validate_transaction(transaction: &Transaction) -> Result<ValidTransaction, Error<T>> {
+		let (block_number, now) = Self::pool_clock();
+		Self::validate_transaction_at(transaction, block_number, now)
+	}
+
+	/// Block number and time the transaction pool judges locks against: the block after the
+	/// latest one, and the time of the latest block since that of the next one is not known yet
+	pub fn pool_clock() -> (u64, u64) {
+		let latest = <LastBlockNumber<T>>::get().saturated_into::<u64>();
+		(latest.saturating_add(1), T::TimeProvider::now().saturated_into::<u64>())
+	}
+
+	/// Check transaction for validity, errors, & race conditions, judging locks at block
+	/// `block_number` and time `now`
 	/// Called by both transaction pool and runtime execution
 	///
 	/// Ensures that:
 	/// - inputs and outputs are not empty
 	/// - all inputs match to existing, unspent and unlocked outputs
+	/// - absolute and relative lock times, and the heights of timelocked scripts
+	///   and HTLC refunds, have expired
+	/// - block reward inputs have reached coinbase maturity
 	/// - each input is used exactly once
 	/// - each output is defined exactly once and has nonzero value
+	/// - each output is locked by a well formed script
 	/// - total output value must not exceed total input value
 	/// - new outputs do not collide with existing ones
 	/// - sum of input and output values does not overflow
 	/// - provided signatures are valid
 	/// - transaction outputs cannot be modified by malicious nodes
-	pub fn validate_transaction(transaction: &Transaction) -> Result<ValidTransaction, &'static str> {
+	fn validate_transaction_at(
+		transaction: &Transaction,
+		block_number: u64,
+		now: u64,
+	) -> Result<ValidTransaction, Error<T>> {
 		// Check basic requirements
-		ensure!(!transaction.inputs.is_empty(), "no inputs");
-		ensure!(!transaction.outputs.is_empty(), "no outputs");
+		ensure!(!transaction.inputs.is_empty(), Error::<T>::NoInputs);
+		ensure!(!transaction.outputs.is_empty(), Error::<T>::NoOutputs);
 
 		{
-			let input_set: BTreeMap<_, ()> =transaction.inputs.iter().map(|input| (input, ())).collect();
-			ensure!(input_set.len() == transaction.inputs.len(), "each input must only be used once");
+			let input_set: BTreeMap<_, ()> = transaction.inputs.iter().map(|input| (input.outpoint, ())).collect();
+			ensure!(input_set.len() == transaction.inputs.len(), Error::<T>::DuplicateInput);
 		}
 		{
 			let output_set: BTreeMap<_, ()> = transaction.outputs.iter().map(|output| (output, ())).collect();
-			ensure!(output_set.len() == transaction.outputs.len(), "each output must be defined only once");
+			ensure!(output_set.len() == transaction.outputs.len(), Error::<T>::DuplicateOutput);
 		}
 
 		let mut total_input: Value = 0;
 		let mut total_output: Value = 0;
-		let mut output_index: u64 = 0;
+		let mut output_index: u32 = 0;
 		let simple_transaction = Self::get_simple_transaction(transaction);
+		let txid = BlakeTwo256::hash(&simple_transaction);
 
 		// Variables sent to transaction pool
 		let mut missing_utxos = Vec::new();
+		let mut pending_locks = Vec::new();
 		let mut new_utxos = Vec::new();
 		let mut reward = 0;
 
+		// Check the absolute lock of the whole transaction
+		if !transaction.lock_time.has_expired(block_number, now) {
+			pending_locks.push(transaction.lock_time);
+		}
+
 		// Check that inputs are valid
 		for input in transaction.inputs.iter() {
 			if let Some(input_utxo) = <UtxoStore>::get(&input.outpoint) {
-				ensure!(sp_io::crypto::sr25519_verify(
-					&Signature::from_raw(*input.sigscript.as_fixed_bytes()),
-					&simple_transaction,
-					&Public::from_h256(input_utxo.pubkey)
-				), "signature must be valid" );
-				total_input = total_input.checked_add(input_utxo.value).ok_or("input value overflow")?;
+				// Timelocked scripts and HTLC refunds wait for their height like an absolute lock
+				let script_lock = script::check(&input_utxo.script, &input.sigscript, &simple_transaction)?;
+				if !script_lock.has_expired(block_number, now) {
+					pending_locks.push(script_lock);
+				}
+
+				// Relative locks count from the block and time the referred UTXO was created
+				let origin = <UtxoOrigins<T>>::get(&input.outpoint).unwrap_or_default();
+				let lock = input.relative_lock.after(
+					origin.block.saturated_into::<u64>(),
+					origin.timestamp.saturated_into::<u64>(),
+				);
+				if !lock.has_expired(block_number, now) {
+					pending_locks.push(lock);
+				}
+
+				// Block rewards wait for coinbase maturity just like a relative lock
+				if origin.coinbase {
+					let maturity = LockTime::Block(T::CoinbaseMaturity::get().saturated_into::<u64>())
+						.after(origin.block.saturated_into::<u64>(), 0);
+					if !maturity.has_expired(block_number, now) {
+						pending_locks.push(maturity);
+					}
+				}
+
+				total_input = total_input.checked_add(input_utxo.value).ok_or(Error::<T>::Overflow)?;
 			} else {
-				missing_utxos.push(input.outpoint.clone().as_fixed_bytes().to_vec());
+				missing_utxos.push(input.outpoint.encode());
 			}
 		}
 
 		// Check that outputs are valid
 		for output in transaction.outputs.iter() {
-			ensure!(output.value > 0, "output value must be nonzero");
-			let hash = BlakeTwo256::hash_of(&(&transaction.encode(), output_index));
-			output_index = output_index.checked_add(1).ok_or("output index overflow")?;
-			ensure!(!<UtxoStore>::contains_key(hash), "output already exists");
-			total_output = total_output.checked_add(output.value).ok_or("output value overflow")?;
-			new_utxos.push(hash.as_fixed_bytes().to_vec());
+			ensure!(output.value > 0, Error::<T>::ZeroOutputValue);
+			script::validate(&output.script)?;
+			let outpoint = OutPoint { txid, index: output_index };
+			output_index = output_index.checked_add(1).ok_or(Error::<T>::Overflow)?;
+			ensure!(!<UtxoStore>::contains_key(outpoint), Error::<T>::OutputAlreadyExists);
+			total_output = total_output.checked_add(output.value).ok_or(Error::<T>::Overflow)?;
+			new_utxos.push(outpoint.encode());
 		}
 
 		// If no race condition, check the math
 		if missing_utxos.is_empty() {
-			ensure!( total_input >= total_output, "output value must not exceed input value");
-			reward = total_input.checked_sub(total_output).ok_or("reward underflow")?;
+			ensure!( total_input >= total_output, Error::<T>::OutputExceedsInput);
+			reward = total_input.checked_sub(total_output).ok_or(Error::<T>::Overflow)?;
 		}
 
+		// Locked transactions wait in the pool until the node provides the tags of their
+		// locks, once they have expired
+		let mut requires = missing_utxos;
+		requires.extend(pending_locks.iter().map(LockTime::pool_tag));
+
 		// Returns transaction details
 		Ok(ValidTransaction {
-			requires: missing_utxos,
+			requires,
 			provides: new_utxos,
 			priority: reward as u64,
 			longevity: TransactionLongevity::max_value(),
@@ -218,60 +579,258 @@ impl<T: Trait> Module<T> {
 	}
 
 	/// Update storage to reflect changes made by transaction
-	/// Where each utxo key is a hash of the entire transaction and its order in the TransactionOutputs vector
+	/// Where each utxo key is the txid and its order in the TransactionOutputs vector
 	fn update_storage(transaction: &Transaction, reward: Value) -> DispatchResult {
 		// Calculate new reward total
 		let new_total = <RewardTotal>::get()
 			.checked_add(reward)
-			.ok_or("Reward overflow")?;
+			.ok_or(Error::<T>::Overflow)?;
 		<RewardTotal>::put(new_total);
 
 		// Removing spent UTXOs
 		for input in &transaction.inputs {
-			<UtxoStore>::remove(input.outpoint);
+			if let Some(spent) = <UtxoStore>::take(input.outpoint) {
+				Self::unindex_owners(&spent, &input.outpoint);
+			}
+			<UtxoOrigins<T>>::remove(input.outpoint);
 		}
 
-		let mut index: u64 = 0;
+		let origin = Self::current_origin();
+		let txid = Self::get_txid(transaction);
+		let mut index: u32 = 0;
 		for output in &transaction.outputs {
-			let hash = BlakeTwo256::hash_of(&(&transaction.encode(), index));
-			index = index.checked_add(1).ok_or("output index overflow")?;
-			<UtxoStore>::insert(hash, output);
+			let outpoint = OutPoint { txid, index };
+			index = index.checked_add(1).ok_or(Error::<T>::Overflow)?;
+			<UtxoStore>::insert(outpoint, output);
+			<UtxoOrigins<T>>::insert(outpoint, origin);
+			Self::index_owners(output, &outpoint);
 		}
 
 		Ok(())
 	}
 
+	/// List `utxo` under the keys named in its script, if the owner index is enabled
+	fn index_owners(utxo: &TransactionOutput, outpoint: &OutPoint) {
+		if T::IndexOwners::get() {
+			for owner in script::owners(&utxo.script) {
+				<UtxosByOwner>::insert(owner, outpoint, ());
+			}
+		}
+	}
+
+	/// Remove a spent `utxo` from the owner index
+	fn unindex_owners(utxo: &TransactionOutput, outpoint: &OutPoint) {
+		if T::IndexOwners::get() {
+			for owner in script::owners(&utxo.script) {
+				<UtxosByOwner>::remove(owner, outpoint);
+			}
+		}
+	}
+
+	/// Up to `count` unspent outputs listed under `owner` in the owner index, in outpoint
+	/// order, starting after `after`. Pass the last outpoint of a page to get the next one.
+	pub fn unspent_by_owner(owner: &H256, after: Option<OutPoint>, count: u32) -> Vec<(OutPoint, TransactionOutput)> {
+		let prefix = <UtxosByOwner as StorageDoubleMapGenerator<H256, OutPoint, ()>>::storage_double_map_final_key1(owner);
+		let mut key = match after {
+			Some(outpoint) => <UtxosByOwner>::hashed_key_for(owner, outpoint),
+			None => prefix.clone(),
+		};
+
+		let mut unspent = Vec::new();
+		while unspent.len() < count as usize {
+			key = match sp_io::storage::next_key(&key).filter(|next| next.starts_with(&prefix)) {
+				Some(next) => next,
+				None => break,
+			};
+			// The identity hasher leaves the encoded outpoint at the end of the key
+			let outpoint = match OutPoint::decode(&mut &key[prefix.len()..]) {
+				Ok(outpoint) => outpoint,
+				Err(_) => continue,
+			};
+			if let Some(utxo) = <UtxoStore>::get(outpoint) {
+				unspent.push((outpoint, utxo));
+			}
+		}
+		unspent
+	}
+
+	/// Weight charged for dispatching `transaction` before its spent UTXOs are read.
+	/// `WeightInfo::spend` covers one signature check per input. Any input may spend a
+	/// multisig UTXO whose signatures are checked against up to `MAX_MULTISIG_KEYS` keys,
+	/// so every input is charged for the further keys, refunded once the scripts are known.
+	///
+	/// Owner index entries are charged when their output is created, for both their insertion
+	/// and their removal once it is spent.
+	pub fn spend_weight(transaction: &Transaction) -> Weight {
+		let extra_keys = transaction.inputs.len().saturating_mul(script::MAX_MULTISIG_KEYS - 1);
+		Self::spend_weight_with_keys(transaction, extra_keys)
+	}
+
+	/// Weight of dispatching `transaction` when its signatures may be checked against
+	/// `extra_keys` keys on top of one per input
+	fn spend_weight_with_keys(transaction: &Transaction, extra_keys: usize) -> Weight {
+		let index_entries = if T::IndexOwners::get() {
+			transaction.outputs
+				.iter()
+				.map(|output| script::owners(&output.script).len())
+				.fold(0usize, |total, owners| total.saturating_add(owners))
+		} else {
+			0
+		};
+
+		T::WeightInfo::spend(
+			transaction.inputs.len().saturated_into(),
+			transaction.outputs.len().saturated_into(),
+		)
+			.saturating_add(T::WeightInfo::check_multisig(extra_keys.saturated_into()))
+			.saturating_add(T::DbWeight::get().writes(index_entries.saturating_mul(2).saturated_into()))
+	}
+
+	/// Number of keys beyond the first of the multisig UTXOs spent by `transaction`
+	fn extra_multisig_keys(transaction: &Transaction) -> usize {
+		transaction.inputs
+			.iter()
+			.filter_map(|input| match <UtxoStore>::get(input.outpoint)?.script {
+				Script::MultiSig { pubkeys, .. } => Some(pubkeys.len().saturating_sub(1)),
+				_ => None,
+			})
+			.fold(0usize, |total, keys| total.saturating_add(keys))
+	}
+
+	/// Re-key legacy UTXOs by `OutPoint` and lock them with a `PayToPubkey` script.
+	///
+	/// Legacy keys hashed the signed transaction, so the transaction id they would have under
+	/// the current rules cannot be recovered from storage. Each one is kept as the txid of a
+	/// single output instead, which still lets wallets holding the old key find the UTXO.
+	fn migrate_to_outpoints() -> Weight {
+		let legacy_utxos: Vec<(H256, LegacyTransactionOutput)> =
+			StorageKeyIterator::<H256, LegacyTransactionOutput, Identity>::new(b"Utxo", b"UtxoStore")
+				.drain()
+				.collect();
+
+		let migrated = legacy_utxos.len() as Weight;
+		for (hash, legacy) in legacy_utxos {
+			<UtxoStore>::insert(OutPoint { txid: hash, index: 0 }, TransactionOutput {
+				value: legacy.value,
+				script: Script::PayToPubkey(legacy.pubkey),
+			});
+		}
+		StorageVersion::put(Releases::V2_0_0);
+
+		T::DbWeight::get().reads_writes(migrated + 1, migrated * 2 + 1)
+	}
+
+	/// Initialize `TotalIssuance` with the value held in UTXOs and undispersed rewards
+	fn migrate_total_issuance() -> Weight {
+		let mut utxos: Weight = 0;
+		let total = <UtxoStore>::iter_values().fold(RewardTotal::get(), |total, utxo| {
+			utxos += 1;
+			total.saturating_add(utxo.value)
+		});
+		TotalIssuance::put(total);
+		StorageVersion::put(Releases::V3_0_0);
+
+		T::DbWeight::get().reads_writes(utxos + 2, 2)
+	}
+
+	/// Build the owner index for existing UTXOs, if it is enabled
+	fn migrate_owner_index() -> Weight {
+		let mut utxos: Weight = 0;
+		let mut entries: Weight = 0;
+		if T::IndexOwners::get() {
+			for (outpoint, utxo) in <UtxoStore>::iter() {
+				utxos += 1;
+				entries += script::owners(&utxo.script).len() as Weight;
+				Self::index_owners(&utxo, &outpoint);
+			}
+		}
+		StorageVersion::put(Releases::V4_0_0);
+
+		T::DbWeight::get().reads_writes(utxos + 1, entries + 1)
+	}
+
 	/// Redistribute combined reward value to block Author
 	fn disperse_reward(author: &Public) {
-		let reward = RewardTotal::take() + T::Issuance::issuance(frame_system::Module::<T>::block_number());
+		let block_number = <frame_system::Module<T>>::block_number();
+		let issuance = T::Issuance::issuance(block_number);
+		let reward = RewardTotal::take() + issuance;
+		TotalIssuance::mutate(|total| *total = total.saturating_add(issuance));
 
 		let utxo = TransactionOutput {
 			value: reward,
-			pubkey: H256::from_slice(author.as_slice()),
+			script: Script::PayToPubkey(H256::from_slice(author.as_slice())),
 		};
 
-		let hash = BlakeTwo256::hash_of(&(&utxo,
-					<frame_system::Module<T>>::block_number().saturated_into::<u64>()));
+		// The reward is the only output of a coinbase transaction without inputs,
+		// made unique by locking it to the current block
+		let coinbase = Transaction {
+			outputs: sp_std::vec![utxo.clone()],
+			lock_time: LockTime::Block(block_number.saturated_into::<u64>()),
+			..Default::default()
+		};
+		let outpoint = OutPoint { txid: Self::get_txid(&coinbase), index: 0 };
+
+		Self::index_owners(&utxo, &outpoint);
+		<UtxoStore>::insert(outpoint, utxo);
+		<UtxoOrigins<T>>::insert(outpoint, UtxoOrigin { coinbase: true, ..Self::current_origin() });
+		Self::deposit_event(Event::RewardsIssued(reward, outpoint));
+	}
 
-		<UtxoStore>::insert(hash, utxo);
-		Self::deposit_event(Event::RewardsIssued(reward, hash));
+	/// Origin of UTXOs created in the current block
+	fn current_origin() -> UtxoOrigin<T::BlockNumber, MomentOf<T>> {
+		UtxoOrigin {
+			block: <frame_system::Module<T>>::block_number(),
+			timestamp: T::TimeProvider::now(),
+			coinbase: false,
+		}
 	}
 
-	// Strips a transaction of its Signature fields by replacing value with ZERO-initialized fixed hash.
+	// Strips a transaction of its Signature fields by replacing value with an empty SigScript.
 	pub fn get_simple_transaction(transaction: &Transaction) -> Vec<u8> {//&'a [u8] {
 		let mut trx = transaction.clone();
 		for input in trx.inputs.iter_mut() {
-			input.sigscript = H512::zero();
+			input.sigscript = SigScript::default();
 		}
 
 		trx.encode()
 	}
 
+	/// Transaction id: the hash of the simple transaction, which is the same
+	/// no matter who signed the transaction or how
+	pub fn get_txid(transaction: &Transaction) -> H256 {
+		BlakeTwo256::hash(&Self::get_simple_transaction(transaction))
+	}
+
+	/// Hashes and preimages revealed by the inputs of a transaction spending hash locked UTXOs
+	pub fn get_revealed_preimages(transaction: &Transaction) -> Vec<(H256, Vec<u8>)> {
+		transaction.inputs.iter()
+			.filter_map(|input| {
+				let input_utxo = <UtxoStore>::get(&input.outpoint)?;
+				script::revealed_preimage(&input_utxo.script, &input.sigscript)
+					.map(|(hash, preimage)| (hash, preimage.to_vec()))
+			})
+			.collect()
+	}
+
+	/// Value of the inputs of `transaction` not spent by its outputs, which goes to the block
+	/// author. `None` if an input is missing or the outputs exceed the inputs.
+	pub fn fee(transaction: &Transaction) -> Option<Value> {
+		let mut total_input: Value = 0;
+		for input in transaction.inputs.iter() {
+			total_input = total_input.checked_add(<UtxoStore>::get(&input.outpoint)?.value)?;
+		}
+		let mut total_output: Value = 0;
+		for output in transaction.outputs.iter() {
+			total_output = total_output.checked_add(output.value)?;
+		}
+		total_input.checked_sub(total_output)
+	}
+
 	/// Helper fn for Transaction Pool
 	/// Checks for race condition, if a certain trx is missing input_utxos in UtxoStore
 	/// If None missing inputs: no race condition, gtg
 	/// if Some(missing inputs): there are missing variables
-	pub fn get_missing_utxos(transaction: &Transaction) -> Vec<&H256> {
+	pub fn get_missing_utxos(transaction: &Transaction) -> Vec<&OutPoint> {
 		let mut missing_utxos = Vec::new();
 		for input in transaction.inputs.iter() {
 			if <UtxoStore>::get(&input.outpoint).is_none() {
@@ -287,7 +846,10 @@ impl<T: Trait> Module<T> {
 mod tests {
 	use super::*;
 
-	use frame_support::{assert_ok, assert_noop, impl_outer_origin, parameter_types, weights::Weight};
+	use sp_core::H512;
+	use crate::script::HashType;
+
+	use frame_support::{assert_ok, assert_noop, impl_outer_event, impl_outer_origin, parameter_types, traits::{OnFinalize, OnRuntimeUpgrade}, weights::Weight};
 	use sp_runtime::{testing::Header, traits::IdentityLookup, Perbill};
 	use sp_core::testing::{KeyStore, SR25519};
 	use sp_core::traits::KeystoreExt;
@@ -296,6 +858,17 @@ mod tests {
 		pub enum Origin for Test {}
 	}
 
+	mod utxo {
+		pub use super::super::Event;
+	}
+
+	impl_outer_event! {
+		pub enum TestEvent for Test {
+			frame_system<T>,
+			utxo,
+		}
+	}
+
 	#[derive(Clone, Eq, PartialEq)]
 	pub struct Test;
 	parameter_types! {
@@ -315,7 +888,7 @@ mod tests {
 		type AccountId = u64;
 		type Lookup = IdentityLookup<Self::AccountId>;
 		type Header = Header;
-		type Event = ();
+		type Event = TestEvent;
 		type BlockHashCount = BlockHashCount;
 		type MaximumBlockWeight = MaximumBlockWeight;
 		type DbWeight = ();
@@ -332,13 +905,33 @@ mod tests {
 		type SystemWeightInfo = ();
 	}
 
+	parameter_types! {
+			pub const MinimumPeriod: u64 = 1;
+	}
+	impl timestamp::Trait for Test {
+		type Moment = u64;
+		type OnTimestampSet = ();
+		type MinimumPeriod = MinimumPeriod;
+		type WeightInfo = ();
+	}
+
+	parameter_types! {
+			pub const CoinbaseMaturity: u64 = 10;
+			pub const IndexOwners: bool = true;
+	}
 	impl Trait for Test {
-		type Event = ();
+		type Event = TestEvent;
 		type BlockAuthor = ();
 		type Issuance = ();
+		type TimeProvider = Timestamp;
+		type CoinbaseMaturity = CoinbaseMaturity;
+		type IndexOwners = IndexOwners;
+		type WeightInfo = ();
 	}
 
 	type Utxo = Module<Test>;
+	type System = frame_system::Module<Test>;
+	type Timestamp = timestamp::Module<Test>;
 
 	// need to manually import this crate since its no include by default
 	use hex_literal::hex;
@@ -346,11 +939,16 @@ mod tests {
 	const ALICE_PHRASE: &str = "news slush supreme milk chapter athlete soap sausage put clutch what kitten";
 	// other random account generated with subkey
 	const KARL_PHRASE: &str = "monitor exhibit resource stumble subject nut valid furnace obscure misery satoshi assume";
-	const GENESIS_UTXO: [u8; 32] = hex!("79eabcbd5ef6e958c6a7851b36da07691c19bda1835a08f875aa286911800999");
+	const GENESIS_TXID: [u8; 32] = hex!("6321b05eca269bc14a752120a10dcb66bc6ad7f9baecf3b466ea14cb9560832e");
+
+	// The only output of the genesis transaction, worth 100 to Alice
+	fn genesis_utxo() -> OutPoint {
+		OutPoint { txid: H256::from(GENESIS_TXID), index: 0 }
+	}
 
 	// This function basically just builds a genesis storage key/value store according to our desired mockup.
 	// We start each test by giving Alice 100 utxo to start with.
-	fn new_test_ext() -> sp_io::TestExternalities {
+	pub fn new_test_ext() -> sp_io::TestExternalities {
 
 		let keystore = KeyStore::new(); // a key storage to store new key pairs during testing
 		let alice_pub_key = keystore.write().sr25519_generate_new(SR25519, Some(ALICE_PHRASE)).unwrap();
@@ -364,22 +962,28 @@ mod tests {
 				genesis_utxos: vec![
 					TransactionOutput {
 						value: 100,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					}
 				],
 				..Default::default()
 			}
-			.build_storage()
+			.build_storage::<Test>()
 			.unwrap()
 			.top,
 		);
 
-		// Print the values to get GENESIS_UTXO
+		// Print the values to get GENESIS_TXID
 		let mut ext = sp_io::TestExternalities::from(t);
 		ext.register_extension(KeystoreExt(keystore));
 		ext
 	}
 
+	// Finalize block `number`, after which the pool judges locks against the block after it
+	fn finalize_block(number: u64) {
+		System::set_block_number(number);
+		Utxo::on_finalize(number);
+	}
+
 	fn new_test_ext_and_keys() -> (sp_io::TestExternalities, Public, Public) {
 
 		let keystore = KeyStore::new(); // a key storage to store new key pairs during testing
@@ -395,17 +999,17 @@ mod tests {
 				genesis_utxos: vec![
 					TransactionOutput {
 						value: 100,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					}
 				],
 				..Default::default()
 			}
-			.build_storage()
+			.build_storage::<Test>()
 			.unwrap()
 			.top,
 		);
 
-		// Print the values to get GENESIS_UTXO
+		// Print the values to get GENESIS_TXID
 		let mut ext = sp_io::TestExternalities::from(t);
 		ext.register_extension(KeystoreExt(keystore));
 		(ext, alice_pub_key, karl_pub_key)
@@ -419,23 +1023,65 @@ mod tests {
 			// Alice wants to send herself a new utxo of value 50.
 			let mut transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::from(GENESIS_UTXO),
-					sigscript: H512::zero(),
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
 				}],
 				outputs: vec![TransactionOutput {
 					value: 50,
-					pubkey: H256::from(alice_pub_key),
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
 				}],
+				..Default::default()
 			};
 
 			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(alice_signature);
-			let new_utxo_hash = BlakeTwo256::hash_of(&(&transaction.encode(), 0 as u64));
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+			let new_utxo = OutPoint { txid: Utxo::get_txid(&transaction), index: 0 };
 
 			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
-			assert!(!UtxoStore::contains_key(H256::from(GENESIS_UTXO)));
-			assert!(UtxoStore::contains_key(new_utxo_hash));
-			assert_eq!(50, UtxoStore::get(new_utxo_hash).unwrap().value);
+			assert!(!UtxoStore::contains_key(genesis_utxo()));
+			assert!(UtxoStore::contains_key(new_utxo));
+			assert_eq!(50, UtxoStore::get(new_utxo).unwrap().value);
+		});
+	}
+
+	#[test]
+	fn test_signature_does_not_change_outpoints() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 50,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+
+			// sr25519 signatures are randomized, so signing twice gives two encodings
+			let first_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			let second_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			let mut resigned = transaction.clone();
+			transaction.inputs[0].sigscript = H512::from(first_signature).into();
+			resigned.inputs[0].sigscript = H512::from(second_signature).into();
+			assert_ne!(transaction.encode(), resigned.encode());
+
+			assert_eq!(Utxo::get_txid(&transaction), Utxo::get_txid(&resigned));
+			assert_eq!(
+				Utxo::validate_transaction(&transaction).unwrap().provides,
+				Utxo::validate_transaction(&resigned).unwrap().provides
+			);
+
+			// Once either copy is included, the other one would recreate the same outputs
+			let new_utxo = OutPoint { txid: Utxo::get_txid(&transaction), index: 0 };
+			assert_ok!(Utxo::spend(Origin::signed(0), resigned));
+			assert!(UtxoStore::contains_key(new_utxo));
+			assert_noop!(Utxo::spend(Origin::signed(0), transaction), Error::<Test>::OutputAlreadyExists);
 		});
 	}
 
@@ -447,19 +1093,21 @@ mod tests {
 			// Karl wants to send himself a new utxo of value 50 out of thin air.
 			let mut transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::zero(),
-					sigscript: H512::zero(),
+					outpoint: OutPoint::default(),
+					sigscript: SigScript::default(),
+					..Default::default()
 				}],
 				outputs: vec![TransactionOutput {
 					value: 50,
-					pubkey: H256::from(karl_pub_key),
+					script: Script::PayToPubkey(H256::from(karl_pub_key)),
 				}],
+				..Default::default()
 			};
 
 			let karl_signature = sp_io::crypto::sr25519_sign(SR25519, &karl_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(karl_signature);
+			transaction.inputs[0].sigscript = H512::from(karl_signature).into();
 
-			assert_noop!(Utxo::spend(Origin::signed(0), transaction), "missing inputs");
+			assert_noop!(Utxo::spend(Origin::signed(0), transaction), Error::<Test>::MissingInputs);
 		});
 	}
 
@@ -468,7 +1116,7 @@ mod tests {
 		new_test_ext().execute_with(|| {
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), Transaction::default()), // an empty trx
-				"no inputs"
+				Error::<Test>::NoInputs
 			);
 
 			assert_noop!(
@@ -477,9 +1125,10 @@ mod tests {
 					Transaction {
 						inputs: vec![TransactionInput::default()], // an empty trx
 						outputs: vec![],
+						..Default::default()
 					}
 				),
-				"no outputs"
+				Error::<Test>::NoOutputs
 			);
 		});
 	}
@@ -492,28 +1141,64 @@ mod tests {
 			let mut transaction = Transaction {
 				inputs: vec![
 					TransactionInput {
-						outpoint: H256::from(GENESIS_UTXO.clone()),
-						sigscript: H512::zero(),
+						outpoint: genesis_utxo(),
+						sigscript: SigScript::default(),
+						..Default::default()
 					},
 					// A double spend of the same UTXO!
 					TransactionInput {
-						outpoint: H256::from(GENESIS_UTXO),
-						sigscript: H512::zero(),
+						outpoint: genesis_utxo(),
+						sigscript: SigScript::default(),
+						..Default::default()
 					},
 				],
 				outputs: vec![TransactionOutput {
 					value: 100,
-					pubkey: H256::from(alice_pub_key),
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
 				}],
+				..Default::default()
 			};
 
 			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(alice_signature.clone());
-			transaction.inputs[1].sigscript = H512::from(alice_signature);
+			transaction.inputs[0].sigscript = H512::from(alice_signature.clone()).into();
+			transaction.inputs[1].sigscript = H512::from(alice_signature).into();
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::DuplicateInput
+			);
+		});
+	}
+
+	#[test]
+	fn attack_by_double_counting_input_with_different_sigscripts() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			let input = TransactionInput {
+				outpoint: genesis_utxo(),
+				sigscript: SigScript::default(),
+				..Default::default()
+			};
+			let mut transaction = Transaction {
+				inputs: vec![input.clone(), input],
+				outputs: vec![TransactionOutput {
+					value: 200,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+
+			// sr25519 signatures are randomized, so the two inputs differ only in their sigscripts
+			let first_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			let second_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(first_signature).into();
+			transaction.inputs[1].sigscript = H512::from(second_signature).into();
+			assert_ne!(transaction.inputs[0], transaction.inputs[1]);
 
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), transaction),
-				"each input must only be used once"
+				Error::<Test>::DuplicateInput
 			);
 		});
 	}
@@ -526,28 +1211,30 @@ mod tests {
 
 			let mut transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::from(GENESIS_UTXO),
-					sigscript: H512::zero(),
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
 				}],
 				outputs: vec![
 					TransactionOutput {
 						value: 100,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					},
 					// Same output defined here!
 					TransactionOutput {
 						value: 100,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					},
 				],
+				..Default::default()
 			};
 
 			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(alice_signature);
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
 
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), transaction),
-				"each output must be defined only once"
+				Error::<Test>::DuplicateOutput
 			);
 		});
 	}
@@ -559,19 +1246,21 @@ mod tests {
 
 			let transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::from(GENESIS_UTXO),
+					outpoint: genesis_utxo(),
 					// Just a random signature!
-					sigscript: H512::random(),
+					sigscript: H512::random().into(),
+					..Default::default()
 				}],
 				outputs: vec![TransactionOutput {
 					value: 100,
-					pubkey: H256::from(alice_pub_key),
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
 				}],
+				..Default::default()
 			};
 
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), transaction),
-				"signature must be valid"
+				Error::<Test>::BadSignature
 			);
 		});
 	}
@@ -583,22 +1272,24 @@ mod tests {
 
 			let mut transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::from(GENESIS_UTXO),
-					sigscript: H512::zero(),
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
 				}],
 				// A 0 value output burns this output forever!
 				outputs: vec![TransactionOutput {
 					value: 0,
-					pubkey: H256::from(alice_pub_key),
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
 				}],
+				..Default::default()
 			};
 
 			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(alice_signature);
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
 
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), transaction),
-				"output value must be nonzero"
+				Error::<Test>::ZeroOutputValue
 			);
 		});
 	}
@@ -610,28 +1301,30 @@ mod tests {
 
 			let mut transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::from(GENESIS_UTXO),
-					sigscript: H512::zero(),
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
 				}],
 				outputs: vec![
 					TransactionOutput {
 						value: Value::max_value(),
-						pubkey:  H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					},
 					// Attempts to do overflow total output value
 					TransactionOutput {
 						value: 10 as Value,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					},
 				],
+				..Default::default()
 			};
 
 			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(alice_signature);
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
 
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), transaction),
-				"output value overflow"
+				Error::<Test>::Overflow
 			);
 		});
 	}
@@ -643,29 +1336,887 @@ mod tests {
 
 			let mut transaction = Transaction {
 				inputs: vec![TransactionInput {
-					outpoint: H256::from(GENESIS_UTXO),
-					sigscript: H512::zero(),
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
 				}],
 				outputs: vec![
 					TransactionOutput {
 						value: 100 as Value,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					},
 					// Creates 2 new utxo out of thin air!
 					TransactionOutput {
 						value: 2 as Value,
-						pubkey: H256::from(alice_pub_key),
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
 					},
 				],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::OutputExceedsInput
+			);
+		});
+	}
+
+	// Spends the genesis UTXO into a single output locked by `script` and returns its outpoint
+	fn lock_genesis_utxo(alice_pub_key: &Public, script: Script) -> OutPoint {
+		let mut transaction = Transaction {
+			inputs: vec![TransactionInput {
+				outpoint: genesis_utxo(),
+				sigscript: SigScript::default(),
+				..Default::default()
+			}],
+			outputs: vec![TransactionOutput {
+				value: 100,
+				script,
+			}],
+			..Default::default()
+		};
+
+		let alice_signature = sp_io::crypto::sr25519_sign(SR25519, alice_pub_key, &transaction.encode()).unwrap();
+		transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+		let new_utxo = OutPoint { txid: Utxo::get_txid(&transaction), index: 0 };
+
+		assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+		new_utxo
+	}
+
+	#[test]
+	fn test_pay_to_pubkey_hash() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+			let alice_pub_key_hash = BlakeTwo256::hash(H256::from(alice_pub_key).as_bytes());
+			let locked_utxo = lock_genesis_utxo(&alice_pub_key, Script::PayToPubkeyHash(alice_pub_key_hash));
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: locked_utxo,
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: vec![H512::from(alice_signature)],
+				// Alice reveals the public key behind the hash
+				redeemer: H256::from(alice_pub_key).as_bytes().to_vec(),
+			};
+
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+			assert!(!UtxoStore::contains_key(locked_utxo));
+		});
+	}
+
+	#[test]
+	fn attack_with_wrong_preimage() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+			let locked_utxo = lock_genesis_utxo(&alice_pub_key, Script::HashLock {
+				hash: BlakeTwo256::hash(b"secret"),
+				pubkey: H256::from(alice_pub_key),
+			});
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: locked_utxo,
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
 			};
 
 			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
-			transaction.inputs[0].sigscript = H512::from(alice_signature);
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: vec![H512::from(alice_signature)],
+				// Not the preimage of the hash lock!
+				redeemer: b"guess".to_vec(),
+			};
 
 			assert_noop!(
 				Utxo::spend(Origin::signed(0), transaction),
-				"output value must not exceed input value"
+				Error::<Test>::PreimageMismatch
 			);
 		});
 	}
+
+	#[test]
+	fn attack_before_timelock_expires() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+			let locked_utxo = lock_genesis_utxo(&alice_pub_key, Script::TimeLock {
+				height: 10,
+				pubkey: H256::from(alice_pub_key),
+			});
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: locked_utxo,
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			// The spend waits in the pool for block 10 like an absolute lock
+			let validity = Utxo::validate_transaction(&transaction).unwrap();
+			assert_eq!(validity.requires, vec![LockTime::Block(10).pool_tag()]);
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction.clone()),
+				Error::<Test>::TransactionLocked
+			);
+
+			finalize_block(9);
+			assert!(Utxo::validate_transaction(&transaction).unwrap().requires.is_empty());
+			System::set_block_number(10);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+		});
+	}
+
+	// Locks the genesis UTXO with a 2-of-3 multisig between Alice, Karl and Carol and returns
+	// Carol's key, along with an unsigned transaction spending the locked UTXO back to Alice
+	fn lock_genesis_utxo_with_multisig(alice_pub_key: &Public, karl_pub_key: &Public) -> (Public, Transaction) {
+		let carol_pub_key = sp_io::crypto::sr25519_generate(SR25519, None);
+		let locked_utxo = lock_genesis_utxo(alice_pub_key, Script::MultiSig {
+			threshold: 2,
+			pubkeys: vec![
+				H256::from(*alice_pub_key),
+				H256::from(*karl_pub_key),
+				H256::from(carol_pub_key),
+			],
+		});
+
+		let transaction = Transaction {
+			inputs: vec![TransactionInput {
+				outpoint: locked_utxo,
+				sigscript: SigScript::default(),
+				..Default::default()
+			}],
+			outputs: vec![TransactionOutput {
+				value: 100,
+				script: Script::PayToPubkey(H256::from(*alice_pub_key)),
+			}],
+			..Default::default()
+		};
+
+		(carol_pub_key, transaction)
+	}
+
+	// Signs `transaction` with each of `signers`, in order, and returns the signatures
+	fn multisign(transaction: &Transaction, signers: &[Public]) -> Vec<H512> {
+		signers.iter()
+			.map(|signer| H512::from(sp_io::crypto::sr25519_sign(SR25519, signer, &transaction.encode()).unwrap()))
+			.collect()
+	}
+
+	#[test]
+	fn test_multisig_spend() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let (carol_pub_key, mut transaction) = lock_genesis_utxo_with_multisig(&alice_pub_key, &karl_pub_key);
+
+			// Any two of the three keys may sign, as long as they keep the script's key order
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: multisign(&transaction, &[karl_pub_key, carol_pub_key]),
+				redeemer: Vec::new(),
+			};
+
+			// The weight charged up front is refunded down to the script's three keys
+			let post_info = Utxo::spend(Origin::signed(0), transaction.clone()).unwrap();
+			assert_eq!(post_info.actual_weight, Some(Utxo::spend_weight_with_keys(&transaction, 2)));
+		});
+	}
+
+	#[test]
+	fn attack_multisig_under_threshold() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let (_carol_pub_key, mut transaction) = lock_genesis_utxo_with_multisig(&alice_pub_key, &karl_pub_key);
+
+			// Only one of the two required signatures!
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: multisign(&transaction, &[alice_pub_key]),
+				redeemer: Vec::new(),
+			};
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::NotEnoughSignatures
+			);
+		});
+	}
+
+	#[test]
+	fn attack_multisig_with_duplicate_signer() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let (_carol_pub_key, mut transaction) = lock_genesis_utxo_with_multisig(&alice_pub_key, &karl_pub_key);
+
+			// Alice signs twice, hoping to count as two signers
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: multisign(&transaction, &[alice_pub_key, alice_pub_key]),
+				redeemer: Vec::new(),
+			};
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::BadSignature
+			);
+		});
+	}
+
+	#[test]
+	fn attack_multisig_with_wrong_key() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let (_carol_pub_key, mut transaction) = lock_genesis_utxo_with_multisig(&alice_pub_key, &karl_pub_key);
+
+			// Mallory is not one of the multisig keys
+			let mallory_pub_key = sp_io::crypto::sr25519_generate(SR25519, None);
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: multisign(&transaction, &[alice_pub_key, mallory_pub_key]),
+				redeemer: Vec::new(),
+			};
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::BadSignature
+			);
+		});
+	}
+
+	#[test]
+	fn attack_by_locking_output_with_duplicate_multisig_keys() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				// Alice alone could satisfy this "2-of-2" multisig
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::MultiSig {
+						threshold: 2,
+						pubkeys: vec![H256::from(alice_pub_key), H256::from(alice_pub_key)],
+					},
+				}],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::DuplicateMultisigKey
+			);
+		});
+	}
+
+	#[test]
+	fn attack_by_locking_output_with_unreachable_threshold() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				// A 2-of-1 multisig can never be spent
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::MultiSig {
+						threshold: 2,
+						pubkeys: vec![H256::from(alice_pub_key)],
+					},
+				}],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::InvalidMultisigThreshold
+			);
+		});
+	}
+
+	#[test]
+	fn test_absolute_lock_time() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				// Not valid before block 5
+				lock_time: LockTime::Block(5),
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			// The transaction pool keeps the transaction waiting for the lock tag
+			let validity = Utxo::validate_transaction(&transaction).unwrap();
+			assert_eq!(validity.requires, vec![(b"lock_time", LockTime::Block(5)).encode()]);
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction.clone()),
+				Error::<Test>::TransactionLocked
+			);
+
+			// Once block 4 is finalized, the pool validates for block 5
+			finalize_block(4);
+			assert!(Utxo::validate_transaction(&transaction).unwrap().requires.is_empty());
+			System::set_block_number(5);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+		});
+	}
+
+	#[test]
+	fn test_locked_transaction_becomes_ready_when_lock_expires() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				lock_time: LockTime::Block(5),
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			// On top of block 3 the transaction waits in the future queue for its lock tag
+			finalize_block(3);
+			let waiting = Utxo::pool_clock();
+			assert_eq!(waiting.0, 4);
+			let requires = Utxo::validate_transaction(&transaction).unwrap().requires;
+			assert_eq!(requires, vec![LockTime::Block(5).pool_tag()]);
+
+			// Block 4 expires the lock, and the node provides its tag to the pool
+			finalize_block(4);
+			let expired = LockTime::expired_pool_tags(waiting, Utxo::pool_clock());
+			assert!(requires.iter().all(|tag| expired.contains(tag)));
+
+			// Had the pool validated it afresh, it would have gone straight to the ready queue
+			assert!(Utxo::validate_transaction(&transaction).unwrap().requires.is_empty());
+		});
+	}
+
+	#[test]
+	fn test_time_lock_pool_tags() {
+		// Time locks share the tag of the next whole second
+		assert_eq!(LockTime::Time(61_001).pool_tag(), LockTime::Time(62_000).pool_tag());
+		assert_ne!(LockTime::Time(61_000).pool_tag(), LockTime::Time(62_000).pool_tag());
+
+		// Which is only provided once the clock has reached that second
+		let tag = LockTime::Time(61_001).pool_tag();
+		assert!(!LockTime::expired_pool_tags((1, 60_000), (2, 61_999)).contains(&tag));
+		assert!(LockTime::expired_pool_tags((2, 61_999), (3, 62_000)).contains(&tag));
+		assert!(!LockTime::expired_pool_tags((3, 62_000), (4, 63_000)).contains(&tag));
+
+		// Every block between the two clocks expires, but not the one judged before
+		assert_eq!(
+			LockTime::expired_pool_tags((4, 0), (6, 999)),
+			vec![LockTime::Block(5).pool_tag(), LockTime::Block(6).pool_tag()],
+		);
+	}
+
+	#[test]
+	fn test_relative_lock_time() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			// Created at time 1_000
+			Timestamp::set_timestamp(1_000);
+			let locked_utxo = lock_genesis_utxo(&alice_pub_key, Script::PayToPubkey(H256::from(alice_pub_key)));
+			assert_eq!(Utxo::utxo_origin(locked_utxo).unwrap().timestamp, 1_000);
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: locked_utxo,
+					sigscript: SigScript::default(),
+					// Only spendable one minute after it was created
+					relative_lock: LockTime::Time(60_000),
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			Timestamp::set_timestamp(60_999);
+			let validity = Utxo::validate_transaction(&transaction).unwrap();
+			assert_eq!(validity.requires, vec![(b"lock_time", LockTime::Time(61_000)).encode()]);
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction.clone()),
+				Error::<Test>::TransactionLocked
+			);
+
+			Timestamp::set_timestamp(61_000);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+			assert!(Utxo::utxo_origin(locked_utxo).is_none());
+		});
+	}
+
+	// Locks the genesis UTXO in an atomic swap from Alice to Karl that Alice can take back
+	// from block 10, and returns an unsigned transaction spending the locked UTXO to `recipient`
+	fn lock_genesis_utxo_with_htlc(alice_pub_key: &Public, karl_pub_key: &Public, recipient: &Public) -> Transaction {
+		let locked_utxo = lock_genesis_utxo(alice_pub_key, Script::Htlc {
+			hash_type: HashType::Sha256,
+			hash: H256::from(sp_io::hashing::sha2_256(b"swap secret")),
+			recipient: H256::from(*karl_pub_key),
+			refund: H256::from(*alice_pub_key),
+			timeout: 10,
+		});
+
+		Transaction {
+			inputs: vec![TransactionInput {
+				outpoint: locked_utxo,
+				sigscript: SigScript::default(),
+				..Default::default()
+			}],
+			outputs: vec![TransactionOutput {
+				value: 100,
+				script: Script::PayToPubkey(H256::from(*recipient)),
+			}],
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_htlc_claim_reveals_preimage() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			// Events are not stored at genesis
+			System::set_block_number(1);
+			let mut transaction = lock_genesis_utxo_with_htlc(&alice_pub_key, &karl_pub_key, &karl_pub_key);
+
+			let karl_signature = sp_io::crypto::sr25519_sign(SR25519, &karl_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: vec![H512::from(karl_signature)],
+				redeemer: b"swap secret".to_vec(),
+			};
+
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+			assert!(System::events().iter().any(|record| record.event == TestEvent::utxo(Event::PreimageRevealed(
+				H256::from(sp_io::hashing::sha2_256(b"swap secret")),
+				b"swap secret".to_vec(),
+			))));
+		});
+	}
+
+	#[test]
+	fn test_htlc_refund_after_timeout() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let mut transaction = lock_genesis_utxo_with_htlc(&alice_pub_key, &karl_pub_key, &alice_pub_key);
+
+			// Alice takes the funds back without knowing the preimage
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			// The refund waits in the pool for the timeout like an absolute lock
+			let validity = Utxo::validate_transaction(&transaction).unwrap();
+			assert_eq!(validity.requires, vec![LockTime::Block(10).pool_tag()]);
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction.clone()),
+				Error::<Test>::TransactionLocked
+			);
+
+			finalize_block(9);
+			assert!(Utxo::validate_transaction(&transaction).unwrap().requires.is_empty());
+			System::set_block_number(10);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+		});
+	}
+
+	#[test]
+	fn attack_htlc_claim_by_refund_key() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let mut transaction = lock_genesis_utxo_with_htlc(&alice_pub_key, &karl_pub_key, &alice_pub_key);
+
+			// Alice learned the preimage, but only Karl may claim with it
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = SigScript {
+				signatures: vec![H512::from(alice_signature)],
+				redeemer: b"swap secret".to_vec(),
+			};
+
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction),
+				Error::<Test>::BadSignature
+			);
+		});
+	}
+
+	#[test]
+	fn test_coinbase_maturity() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			// Alice mines block 1 and collects 50 in fees
+			System::set_block_number(1);
+			RewardTotal::put(50);
+			Utxo::disperse_reward(&alice_pub_key);
+			let coinbase = Transaction {
+				outputs: vec![TransactionOutput {
+					value: 50,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				lock_time: LockTime::Block(1),
+				..Default::default()
+			};
+			let reward_utxo = OutPoint { txid: Utxo::get_txid(&coinbase), index: 0 };
+			assert!(Utxo::utxo_origin(reward_utxo).unwrap().coinbase);
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: reward_utxo,
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 50,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			// Premature spends wait in the pool until block 11
+			finalize_block(9);
+			System::set_block_number(10);
+			let validity = Utxo::validate_transaction(&transaction).unwrap();
+			assert_eq!(validity.requires, vec![(b"lock_time", LockTime::Block(11)).encode()]);
+			assert_noop!(
+				Utxo::spend(Origin::signed(0), transaction.clone()),
+				Error::<Test>::TransactionLocked
+			);
+
+			System::set_block_number(11);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+		});
+	}
+
+	#[test]
+	fn test_coinbase_spend_goes_through_at_maturity() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+
+			// Alice mines block 1 and collects 50 in fees
+			System::set_block_number(1);
+			RewardTotal::put(50);
+			Utxo::disperse_reward(&alice_pub_key);
+			finalize_block(1);
+			let reward_utxo = OutPoint {
+				txid: Utxo::get_txid(&Transaction {
+					outputs: vec![TransactionOutput {
+						value: 50,
+						script: Script::PayToPubkey(H256::from(alice_pub_key)),
+					}],
+					lock_time: LockTime::Block(1),
+					..Default::default()
+				}),
+				index: 0,
+			};
+
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: reward_utxo,
+					sigscript: SigScript::default(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 50,
+					script: Script::PayToPubkey(H256::from(alice_pub_key)),
+				}],
+				..Default::default()
+			};
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+
+			// Submitted right away, the spend waits in the pool through blocks 2 to 10,
+			// each of which would fail to include it
+			let requires = Utxo::validate_transaction(&transaction).unwrap().requires;
+			assert_eq!(requires, vec![LockTime::Block(11).pool_tag()]);
+			let mut clock = Utxo::pool_clock();
+			for block in 2..=10 {
+				System::set_block_number(block);
+				assert_noop!(
+					Utxo::spend(Origin::signed(0), transaction.clone()),
+					Error::<Test>::TransactionLocked
+				);
+				finalize_block(block);
+
+				let next_clock = Utxo::pool_clock();
+				let expired = LockTime::expired_pool_tags(clock, next_clock);
+				assert_eq!(requires.iter().all(|tag| expired.contains(tag)), block == 10);
+				clock = next_clock;
+			}
+
+			// Promoted once block 10 is in, the spend goes into block 11
+			assert!(Utxo::validate_transaction(&transaction).unwrap().requires.is_empty());
+			System::set_block_number(11);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction.clone()));
+			assert!(!UtxoStore::contains_key(reward_utxo));
+			assert!(UtxoStore::contains_key(OutPoint { txid: Utxo::get_txid(&transaction), index: 0 }));
+		});
+	}
+
+	#[test]
+	fn test_spend_weight() {
+		new_test_ext().execute_with(|| {
+			let input = TransactionInput {
+				sigscript: H512::zero().into(),
+				..Default::default()
+			};
+			let transaction = Transaction {
+				inputs: vec![input.clone()],
+				outputs: vec![TransactionOutput::default()],
+				..Default::default()
+			};
+			let weight = Utxo::spend_weight(&transaction);
+
+			// Each extra input or output costs more
+			let mut more_inputs = transaction.clone();
+			more_inputs.inputs.push(input);
+			assert!(Utxo::spend_weight(&more_inputs) > weight);
+			let mut more_outputs = transaction.clone();
+			more_outputs.outputs.push(TransactionOutput::default());
+			assert!(Utxo::spend_weight(&more_outputs) > weight);
+
+			// Signatures cost nothing extra by themselves
+			let mut more_signatures = transaction.clone();
+			more_signatures.inputs[0].sigscript.signatures.push(H512::zero());
+			assert_eq!(Utxo::spend_weight(&more_signatures), weight);
+
+			// Every input is charged up front for the keys of the largest multisig script
+			assert_eq!(weight, Utxo::spend_weight_with_keys(&transaction, script::MAX_MULTISIG_KEYS - 1));
+			assert!(Utxo::spend_weight_with_keys(&transaction, 0) < weight);
+
+			// Spending a multisig UTXO is checked against every key of its script
+			let multisig = |outpoint, keys: u8| {
+				UtxoStore::insert(outpoint, TransactionOutput {
+					value: 100,
+					script: Script::MultiSig {
+						threshold: 1,
+						pubkeys: (0..keys).map(H256::repeat_byte).collect(),
+					},
+				});
+				let mut spend = transaction.clone();
+				spend.inputs[0].outpoint = outpoint;
+				Utxo::extra_multisig_keys(&spend)
+			};
+			assert_eq!(Utxo::extra_multisig_keys(&transaction), 0);
+			assert_eq!(multisig(OutPoint { txid: H256::repeat_byte(1), index: 0 }, 1), 0);
+			assert_eq!(multisig(OutPoint { txid: H256::repeat_byte(1), index: 1 }, 3), 2);
+		});
+	}
+
+	#[test]
+	fn test_fee() {
+		new_test_ext().execute_with(|| {
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput { value: 60, ..Default::default() }],
+				..Default::default()
+			};
+			assert_eq!(Utxo::fee(&transaction), Some(40));
+
+			transaction.outputs[0].value = 101;
+			assert_eq!(Utxo::fee(&transaction), None);
+
+			transaction.outputs[0].value = 60;
+			transaction.inputs[0].outpoint.index = 1;
+			assert_eq!(Utxo::fee(&transaction), None);
+		});
+	}
+
+	#[test]
+	fn test_migrate_legacy_utxos() {
+		sp_io::TestExternalities::default().execute_with(|| {
+			let legacy_utxo = H256::random();
+			let pubkey = H256::random();
+			frame_support::storage::migration::put_storage_value(
+				b"Utxo",
+				b"UtxoStore",
+				legacy_utxo.as_bytes(),
+				LegacyTransactionOutput { value: 100, pubkey },
+			);
+
+			Utxo::on_runtime_upgrade();
+
+			let migrated = TransactionOutput { value: 100, script: Script::PayToPubkey(pubkey) };
+			assert_eq!(StorageVersion::get(), Releases::V4_0_0);
+			assert_eq!(UtxoStore::get(OutPoint { txid: legacy_utxo, index: 0 }), Some(migrated.clone()));
+			assert_eq!(UtxoStore::iter().count(), 1);
+			assert_eq!(Utxo::total_issuance(), 100);
+			assert_eq!(
+				Utxo::unspent_by_owner(&pubkey, None, 10),
+				vec![(OutPoint { txid: legacy_utxo, index: 0 }, migrated)]
+			);
+		});
+	}
+
+	#[test]
+	fn test_total_issuance() {
+		new_test_ext().execute_with(|| {
+			let alice_pub_key = sp_io::crypto::sr25519_public_keys(SR25519)[0];
+			assert_eq!(Utxo::total_issuance(), 100);
+
+			// Fees collected into the reward pot are paid back out, not newly issued
+			RewardTotal::put(50);
+			Utxo::disperse_reward(&alice_pub_key);
+			assert_eq!(Utxo::total_issuance(), 100);
+		});
+	}
+
+	#[test]
+	fn test_owner_index() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let alice = H256::from(alice_pub_key);
+			let karl = H256::from(karl_pub_key);
+			let genesis_output = UtxoStore::get(genesis_utxo()).unwrap();
+			assert_eq!(Utxo::unspent_by_owner(&alice, None, 10), vec![(genesis_utxo(), genesis_output)]);
+			assert!(Utxo::unspent_by_owner(&karl, None, 10).is_empty());
+
+			// Alice pays Karl twice and keeps the change
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					..Default::default()
+				}],
+				outputs: vec![
+					TransactionOutput { value: 10, script: Script::PayToPubkey(karl) },
+					TransactionOutput { value: 20, script: Script::PayToPubkey(karl) },
+					TransactionOutput { value: 70, script: Script::PayToPubkey(alice) },
+				],
+				..Default::default()
+			};
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+			let txid = Utxo::get_txid(&transaction);
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction.clone()));
+
+			assert_eq!(
+				Utxo::unspent_by_owner(&alice, None, 10),
+				vec![(OutPoint { txid, index: 2 }, transaction.outputs[2].clone())]
+			);
+
+			// Karl pages through his outputs one at a time
+			let first_page = Utxo::unspent_by_owner(&karl, None, 1);
+			assert_eq!(first_page, vec![(OutPoint { txid, index: 0 }, transaction.outputs[0].clone())]);
+			let second_page = Utxo::unspent_by_owner(&karl, Some(first_page[0].0), 1);
+			assert_eq!(second_page, vec![(OutPoint { txid, index: 1 }, transaction.outputs[1].clone())]);
+			assert!(Utxo::unspent_by_owner(&karl, Some(second_page[0].0), 1).is_empty());
+
+			// Block rewards are listed too
+			RewardTotal::put(5);
+			Utxo::disperse_reward(&karl_pub_key);
+			assert_eq!(Utxo::unspent_by_owner(&karl, None, 10).len(), 3);
+		});
+	}
+
+	#[test]
+	fn test_multisig_outputs_are_listed_under_every_key() {
+		let (mut test_ext, alice_pub_key, karl_pub_key) = new_test_ext_and_keys();
+		test_ext.execute_with(|| {
+			let alice = H256::from(alice_pub_key);
+			let karl = H256::from(karl_pub_key);
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint: genesis_utxo(),
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::MultiSig { threshold: 1, pubkeys: vec![alice, karl] },
+				}],
+				..Default::default()
+			};
+			let alice_signature = sp_io::crypto::sr25519_sign(SR25519, &alice_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(alice_signature).into();
+			let outpoint = OutPoint { txid: Utxo::get_txid(&transaction), index: 0 };
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+
+			assert_eq!(Utxo::unspent_by_owner(&alice, None, 10)[0].0, outpoint);
+			assert_eq!(Utxo::unspent_by_owner(&karl, None, 10)[0].0, outpoint);
+
+			// Spending it removes it from both
+			let mut transaction = Transaction {
+				inputs: vec![TransactionInput {
+					outpoint,
+					..Default::default()
+				}],
+				outputs: vec![TransactionOutput {
+					value: 100,
+					script: Script::PayToPubkeyHash(BlakeTwo256::hash(karl.as_bytes())),
+				}],
+				..Default::default()
+			};
+			let karl_signature = sp_io::crypto::sr25519_sign(SR25519, &karl_pub_key, &transaction.encode()).unwrap();
+			transaction.inputs[0].sigscript = H512::from(karl_signature).into();
+			assert_ok!(Utxo::spend(Origin::signed(0), transaction));
+
+			assert!(Utxo::unspent_by_owner(&alice, None, 10).is_empty());
+			assert!(Utxo::unspent_by_owner(&karl, None, 10).is_empty());
+			assert_eq!(Utxo::unspent_by_owner(&BlakeTwo256::hash(karl.as_bytes()), None, 10).len(), 1);
+		});
+	}
 }
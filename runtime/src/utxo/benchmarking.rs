@@ -0,0 +1,113 @@
+//! Benchmarks for the UTXO pallet, used to derive `WeightInfo`
+
+#![cfg(feature = "runtime-benchmarks")]
+
+use super::*;
+
+use frame_benchmarking::benchmarks;
+use frame_system::RawOrigin;
+use sp_core::{crypto::KeyTypeId, H512};
+use sp_std::prelude::*;
+
+use crate::script::MAX_MULTISIG_KEYS;
+
+/// Key type the benchmarks generate and sign with
+const KEY_TYPE: KeyTypeId = KeyTypeId(*b"utxo");
+
+/// Upper bound on the inputs and outputs benchmarked. Weights for larger transactions are
+/// extrapolated linearly.
+const MAX_INPUTS_OR_OUTPUTS: u32 = 100;
+
+// Generate a new sr25519 key in the keystore and return it as a script key
+fn new_pubkey() -> Public {
+	sp_io::crypto::sr25519_generate(KEY_TYPE, None)
+}
+
+// Sign `message` with a key previously generated by `new_pubkey`
+fn sign(pubkey: &Public, message: &[u8]) -> H512 {
+	H512::from(sp_io::crypto::sr25519_sign(KEY_TYPE, pubkey, message).expect("key was generated in the keystore; qed"))
+}
+
+// Put `count` UTXOs locked by `script` into storage and return the inputs spending them
+fn setup_inputs(script: &Script, count: u32) -> Vec<TransactionInput> {
+	(0..count).map(|index| {
+		let outpoint = OutPoint { txid: H256::repeat_byte(1), index };
+		<UtxoStore>::insert(outpoint, TransactionOutput {
+			value: MAX_INPUTS_OR_OUTPUTS as Value,
+			script: script.clone(),
+		});
+		TransactionInput { outpoint, ..Default::default() }
+	}).collect()
+}
+
+benchmarks! {
+	_ { }
+
+	// Every input is a pay to pubkey spend with one signature check,
+	// every output creates a new UTXO for a different key
+	spend {
+		let i in 1 .. MAX_INPUTS_OR_OUTPUTS;
+		let o in 1 .. MAX_INPUTS_OR_OUTPUTS;
+
+		let pubkey = new_pubkey();
+		let script = Script::PayToPubkey(H256::from(pubkey));
+		let mut transaction = Transaction {
+			inputs: setup_inputs(&script, i),
+			outputs: (0..o).map(|n| TransactionOutput {
+				value: 1,
+				script: Script::PayToPubkey(H256::from_low_u64_be(n.into())),
+			}).collect(),
+			..Default::default()
+		};
+		let signature = sign(&pubkey, &transaction.encode());
+		for input in transaction.inputs.iter_mut() {
+			input.sigscript = signature.into();
+		}
+		let txid = Module::<T>::get_txid(&transaction);
+	}: _(RawOrigin::None, transaction)
+	verify {
+		assert!(<UtxoStore>::contains_key(OutPoint { txid, index: o - 1 }));
+	}
+
+	// One multisig input of `k` keys whose signature is by the last key, so the signature is
+	// checked against every key. More signatures would not try more keys, as each key is
+	// tried once at most.
+	check_multisig {
+		let k in 1 .. MAX_MULTISIG_KEYS as u32;
+
+		let keys: Vec<Public> = (0..k).map(|_| new_pubkey()).collect();
+		let script = Script::MultiSig {
+			threshold: 1,
+			pubkeys: keys.iter().cloned().map(H256::from).collect(),
+		};
+		let message = b"utxo multisig benchmark".to_vec();
+		let last = keys.last().expect("at least one key is benchmarked; qed");
+		let sigscript = SigScript {
+			signatures: sp_std::vec![sign(last, &message)],
+			redeemer: Vec::new(),
+		};
+	}: {
+		script::check(&script, &sigscript, &message).map_err(Error::<T>::from)?;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utxo::tests::{new_test_ext, Test};
+	use frame_support::assert_ok;
+
+	#[test]
+	fn spend() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_spend::<Test>());
+		});
+	}
+
+	#[test]
+	fn check_multisig() {
+		new_test_ext().execute_with(|| {
+			assert_ok!(test_benchmark_check_multisig::<Test>());
+		});
+	}
+}
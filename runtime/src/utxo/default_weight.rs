@@ -0,0 +1,25 @@
+//! Weights for the UTXO pallet
+//!
+//! These are estimates, not benchmark output. Regenerate them by building the node with the
+//! `runtime-benchmarks` feature and running
+//! `utxo-workshop benchmark --pallet utxo --extrinsic '*' --steps 20 --repeat 10`.
+
+use frame_support::weights::{Weight, constants::RocksDbWeight as DbWeight};
+
+impl super::WeightInfo for () {
+	fn spend(i: u32, o: u32) -> Weight {
+		(0 as Weight)
+			.saturating_add((160675000 as Weight).saturating_mul(i as Weight))
+			.saturating_add((11704000 as Weight).saturating_mul(o as Weight))
+			.saturating_add(DbWeight::get().reads(2 as Weight))
+			.saturating_add(DbWeight::get().reads((2 as Weight).saturating_mul(i as Weight)))
+			.saturating_add(DbWeight::get().reads((1 as Weight).saturating_mul(o as Weight)))
+			.saturating_add(DbWeight::get().writes(1 as Weight))
+			.saturating_add(DbWeight::get().writes((2 as Weight).saturating_mul(i as Weight)))
+			.saturating_add(DbWeight::get().writes((2 as Weight).saturating_mul(o as Weight)))
+	}
+	fn check_multisig(k: u32) -> Weight {
+		(0 as Weight)
+			.saturating_add((104650000 as Weight).saturating_mul(k as Weight))
+	}
+}
@@ -0,0 +1,40 @@
+use sp_core::H256;
+use frame_support::weights::Weight;
+use sp_runtime::{DispatchError, transaction_validity::ValidTransaction};
+use sp_std::vec::Vec;
+
+use crate::utxo::{OutPoint, Transaction, TransactionOutput, Value};
+
+sp_api::decl_runtime_apis! {
+	/// Read-only access to the UTXO pallet for wallets and other clients
+	pub trait UtxoApi {
+		/// The unspent output at `outpoint`, if any
+		fn get_utxo(outpoint: OutPoint) -> Option<TransactionOutput>;
+
+		/// Up to `count` unspent outputs whose script names `owner`, in outpoint order,
+		/// starting after the outpoint `after`. Empty unless the owner index is enabled.
+		fn list_unspent(owner: H256, after: Option<OutPoint>, count: u32) -> Vec<(OutPoint, TransactionOutput)>;
+
+		/// Check `transaction` against the current UTXO set. On failure the module error
+		/// says which check was violated.
+		fn validate(transaction: Transaction) -> Result<ValidTransaction, DispatchError>;
+
+		/// The encoding of `transaction` with its sigscripts stripped, which is what every
+		/// input's signatures sign
+		fn simple_transaction_bytes(transaction: Transaction) -> Vec<u8>;
+
+		/// Value of the inputs of `transaction` not spent by its outputs, which goes to the
+		/// block author. `None` if an input is missing or the outputs exceed the inputs.
+		fn fee(transaction: Transaction) -> Option<Value>;
+
+		/// Weight charged for dispatching `transaction`
+		fn spend_weight(transaction: Transaction) -> Weight;
+
+		/// Total value created at genesis and by block rewards so far
+		fn total_issuance() -> Value;
+
+		/// Block number and time the transaction pool judges locks against on top of this
+		/// block. Locks expire in the pool as this clock moves from block to block.
+		fn lock_clock() -> (u64, u64);
+	}
+}